@@ -0,0 +1,302 @@
+use std::f32::consts::PI;
+use std::fs;
+
+use crate::*;
+
+/// Plugin responsible for loading the abilities usable in combat from assets/abilities.json.
+///
+/// The player has the abilities listed by ID in [`Player::abilities`]. [`add_combat_stuff`] gives each of them a
+/// button, and [`CombatState`] works out where they can be used and what they hit from their [`Ability`].
+pub struct AbilityPlugin;
+
+impl Plugin for AbilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AbilityBook>();
+    }
+}
+
+/// Which tiles an ability can be used on, relative to the player
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Targeting {
+    /// Any tile, however far away
+    Anywhere,
+    /// Any tile within `range` of the player, other than their own
+    Within,
+    /// Tiles exactly `range` away from the player in a straight line
+    Line,
+    /// The player's own tile
+    Myself,
+}
+
+/// The tiles an ability hits around the one it's used on
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum AreaShape {
+    /// Only the targeted tile
+    #[default]
+    Single,
+    /// Every tile within this many tiles of the target
+    Radius(i32),
+    /// This many tiles in a straight line, starting at the target and carrying on away from the player. Always hits at
+    /// least the target
+    Line(i32),
+    /// Spreads out from the target away from the player, reaching this many tiles deep, the target included. Always
+    /// hits at least the target
+    Cone(i32),
+    /// Tiles exactly this many tiles away from the target, leaving the inside untouched
+    Ring(i32),
+}
+
+/// Half the angle a [`AreaShape::Cone`] spreads over
+const CONE_HALF_ANGLE: f32 = PI / 6.0;
+/// Cones thrown straight at a neighbour have tiles lying exactly on their edges, two tiles out. Rounding can put those
+/// a hair outside the cone, so they're let in by this much, in radians
+const CONE_EDGE_LEEWAY: f32 = 1e-3;
+
+impl AreaShape {
+    /// The tiles hit when an ability is used on `target` by someone standing on `from`
+    pub fn tiles(&self, from: &HexCoord, target: &HexCoord) -> Vec<HexCoord> {
+        let within = |radius: i32| {
+            (target.r - radius..=target.r + radius)
+                .flat_map(move |r| (target.q - radius..=target.q + radius).map(move |q| HexCoord::new(q, r)))
+                .filter(move |coord| hex_distance(coord, target) <= radius)
+        };
+        // Lines and cones point the way the ability was thrown. Used on the player's own tile they have no direction,
+        // and only hit that tile
+        let direction = (target.to_world() - from.to_world()).normalize_or_zero();
+
+        match *self {
+            AreaShape::Single => vec![*target],
+            AreaShape::Radius(radius) => within(radius).collect(),
+            AreaShape::Ring(radius) => within(radius)
+                .filter(|coord| hex_distance(coord, target) == radius)
+                .collect(),
+            AreaShape::Line(_) | AreaShape::Cone(_) if direction == Vec2::ZERO => vec![*target],
+            AreaShape::Line(length) => {
+                let length = length.max(1);
+                let end = target.to_world() + direction * (length - 1) as f32 * HORIZONTAL_SPACING;
+                hex_line(target, &HexCoord::world_to_hex(end.x, end.y))
+                    .into_iter()
+                    .take(length as usize)
+                    .collect()
+            }
+            AreaShape::Cone(length) => within(length.max(1) - 1)
+                .filter(|coord| {
+                    let offset = coord.to_world() - target.to_world();
+                    coord == target || direction.angle_between(offset).abs() <= CONE_HALF_ANGLE + CONE_EDGE_LEEWAY
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Whether, and how much, the player is hurt by their own ability when standing in its area
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum FriendlyFire {
+    #[default]
+    Off,
+    /// The player takes as much damage as an enemy standing where they are would
+    Full,
+    /// The player takes this fraction of the damage an enemy standing where they are would
+    Reduced(f32),
+}
+
+impl FriendlyFire {
+    /// Multiplies the damage the player takes
+    pub fn multiplier(&self) -> f32 {
+        match *self {
+            FriendlyFire::Off => 0.0,
+            FriendlyFire::Full => 1.0,
+            FriendlyFire::Reduced(fraction) => fraction,
+        }
+    }
+}
+
+/// Damage dealt to every enemy an ability hits, before the high ground bonus
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct DamageFormula {
+    #[serde(default)]
+    pub flat: f32,
+    /// Extra damage for every point of the player's damage stat
+    #[serde(default)]
+    pub per_damage: f32,
+}
+
+impl DamageFormula {
+    pub fn damage(&self, stats: &Stats) -> f32 {
+        self.flat + self.per_damage * stats.damage as f32
+    }
+}
+
+/// An entry of assets/abilities.json
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Ability {
+    /// Shown on the ability's button
+    pub name: String,
+    /// Shown on the ability's button while it's selected
+    pub active_text: String,
+    /// Image of the ability's button, relative to the assets folder
+    #[serde(default)]
+    pub icon: Option<String>,
+    pub targeting: Targeting,
+    /// See [`Targeting`]. Unused by [`Targeting::Anywhere`]
+    #[serde(default)]
+    pub range: i32,
+    /// Energy spent on using the ability
+    #[serde(default)]
+    pub energy_cost: i32,
+    #[serde(default)]
+    pub damage: DamageFormula,
+    /// The kind of damage the ability deals, see [`DamageStats::resistances`]
+    #[serde(default)]
+    pub element: Element,
+    #[serde(default)]
+    pub area: AreaShape,
+    /// Fraction of the damage lost for every tile between the target and whoever is hit
+    #[serde(default)]
+    pub falloff: f32,
+    #[serde(default)]
+    pub friendly_fire: FriendlyFire,
+    /// Applied to everyone the ability hits
+    #[serde(default)]
+    pub effects: Vec<StatusEffect>,
+    /// Applied to the player whenever they use the ability, wherever it lands
+    #[serde(default)]
+    pub self_effects: Vec<StatusEffect>,
+    /// Animation of the player's [`RiggedEntity`] played when the ability is used
+    #[serde(default)]
+    pub animation: Option<usize>,
+    /// Whether the spell sphere is shown over the targeted tile
+    #[serde(default)]
+    pub projectile: bool,
+}
+
+impl Ability {
+    /// Whether the player standing on `from` can use the ability on `target`
+    pub fn can_target(&self, from: &HexCoord, target: &HexCoord) -> bool {
+        match self.targeting {
+            Targeting::Anywhere => true,
+            Targeting::Within => from != target && hex_distance(from, target) <= self.range,
+            Targeting::Line => straight_line_tiles(from, self.range).contains(target),
+            Targeting::Myself => from == target,
+        }
+    }
+
+    /// The tiles hit when the player standing on `from` uses the ability on `target`
+    pub fn affected_tiles(&self, from: &HexCoord, target: &HexCoord) -> Vec<HexCoord> {
+        self.area.tiles(from, target)
+    }
+
+    /// Damage dealt to an enemy standing on `coord` when the ability is used on `target`, before the high ground
+    /// bonus
+    pub fn damage_at(&self, stats: &Stats, target: &HexCoord, coord: &HexCoord) -> f32 {
+        let falloff = (1.0 - self.falloff * hex_distance(target, coord) as f32).max(0.0);
+        self.damage.damage(stats) * falloff
+    }
+}
+
+#[derive(Resource)]
+/// Every ability there is, keyed by ID. Loaded from assets/abilities.json
+pub struct AbilityBook(pub HashMap<String, Ability>);
+
+impl Default for AbilityBook {
+    fn default() -> Self {
+        let manifest = fs::read_to_string("assets/abilities.json").expect("Something went wrong reading the file");
+        AbilityBook(serde_json::from_str(&manifest).unwrap())
+    }
+}
+
+impl AbilityBook {
+    /// The abilities of the player that the book has, in the order the player lists them
+    pub fn player_abilities<'a>(&'a self, player: &'a Player) -> impl Iterator<Item = &'a Ability> {
+        player.abilities.iter().filter_map(|id| self.0.get(id))
+    }
+}
+
+/// The abilities a new character starts with
+pub fn default_abilities() -> Vec<String> {
+    vec![
+        "fireball".to_string(),
+        "smack".to_string(),
+        "run_smack".to_string(),
+        "venom_dart".to_string(),
+        "brace".to_string(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiles(shape: AreaShape, from: (i32, i32), target: (i32, i32)) -> Vec<(i32, i32)> {
+        let mut tiles: Vec<(i32, i32)> = shape
+            .tiles(&HexCoord::new(from.0, from.1), &HexCoord::new(target.0, target.1))
+            .iter()
+            .map(|coord| (coord.q, coord.r))
+            .collect();
+        tiles.sort();
+        tiles
+    }
+
+    #[test]
+    fn radius_and_ring_surround_the_target() {
+        let around = vec![(1, 3), (2, 2), (2, 4), (3, 2), (3, 3), (3, 4)];
+        assert_eq!(tiles(AreaShape::Ring(1), (0, 0), (2, 3)), around);
+        assert_eq!(tiles(AreaShape::Radius(1), (0, 0), (2, 3)).len(), 7);
+        assert_eq!(tiles(AreaShape::Ring(0), (0, 0), (2, 3)), vec![(2, 3)]);
+        assert_eq!(tiles(AreaShape::Radius(0), (0, 0), (2, 3)), vec![(2, 3)]);
+    }
+
+    #[test]
+    fn lines_carry_on_away_from_the_player() {
+        // Down and to the right, from the neighbouring tile
+        assert_eq!(tiles(AreaShape::Line(3), (2, 2), (2, 3)), vec![(2, 3), (3, 4), (3, 5)]);
+        // Up and to the left
+        assert_eq!(tiles(AreaShape::Line(3), (3, 4), (2, 3)), vec![(1, 1), (2, 2), (2, 3)]);
+        // Along the row
+        assert_eq!(tiles(AreaShape::Line(3), (1, 2), (2, 2)), vec![(2, 2), (3, 2), (4, 2)]);
+        assert_eq!(tiles(AreaShape::Line(0), (2, 2), (2, 3)), vec![(2, 3)]);
+        // Used on the player's own tile there's no way for the line to go
+        assert_eq!(tiles(AreaShape::Line(3), (2, 3), (2, 3)), vec![(2, 3)]);
+    }
+
+    #[test]
+    fn cones_spread_out_away_from_the_player() {
+        // Tiles two out on either side lie right on the edges of the cone
+        assert_eq!(
+            tiles(AreaShape::Cone(3), (2, 2), (2, 3)),
+            vec![(2, 3), (2, 5), (3, 4), (3, 5), (4, 4)]
+        );
+        assert_eq!(
+            tiles(AreaShape::Cone(3), (1, 2), (2, 2)),
+            vec![(2, 2), (3, 1), (3, 2), (3, 3), (4, 2)]
+        );
+        assert_eq!(tiles(AreaShape::Cone(2), (2, 2), (2, 3)), vec![(2, 3), (3, 4)]);
+        assert_eq!(tiles(AreaShape::Cone(0), (2, 2), (2, 3)), vec![(2, 3)]);
+    }
+
+    #[test]
+    fn damage_falls_off_away_from_the_target() {
+        let ability: Ability = serde_json::from_str(
+            r#"{
+                "name": "Boom",
+                "active_text": "Booming",
+                "targeting": "Anywhere",
+                "damage": { "flat": 10.0 },
+                "area": { "Radius": 5 },
+                "falloff": 0.25
+            }"#,
+        )
+        .unwrap();
+        let stats = Stats {
+            speed: 0,
+            damage: 0,
+            health: 0,
+        };
+        let target = HexCoord::new(4, 4);
+        assert_eq!(ability.damage_at(&stats, &target, &target), 10.0);
+        assert_eq!(ability.damage_at(&stats, &target, &HexCoord::new(5, 4)), 7.5);
+        assert_eq!(ability.damage_at(&stats, &target, &HexCoord::new(6, 4)), 5.0);
+        // Too far away to be hurt at all, rather than healed
+        assert_eq!(ability.damage_at(&stats, &target, &HexCoord::new(9, 4)), 0.0);
+    }
+}
@@ -1,5 +1,3 @@
-use bevy_mod_picking::prelude::RaycastPickCamera;
-
 use crate::*;
 
 use std::collections::hash_map::DefaultHasher;
@@ -49,12 +47,20 @@ pub struct RiggedEntity {
     idle_state: IdleState,
     current_animation: Option<CurrentAnimation>,
     pending_animation: Option<usize>,
+    /// Playback speed of the pending animation
+    pending_speed: f32,
 }
 
 impl RiggedEntity {
     pub fn pend(&mut self, idx: usize) {
+        self.pend_with_speed(idx, 1.0);
+    }
+
+    /// Pends an animation played `speed` times faster than normal, e.g. running in step with faster movement
+    pub fn pend_with_speed(&mut self, idx: usize, speed: f32) {
         assert!((0..=10).contains(&idx));
         self.pending_animation = Some(idx);
+        self.pending_speed = speed;
     }
 }
 
@@ -108,6 +114,7 @@ pub fn spawn_rigged_entity(
                         current_animation: None,
                         // 3 is idle_unarmed which will be default
                         pending_animation: Some(3),
+                        pending_speed: 1.0,
                     })
                     .insert(Name::new("Kraug"))
                     .insert(HashMarker(pair_hash))
@@ -134,7 +141,6 @@ pub fn spawn_rigged_entity(
                         zoom_upper_limit: Some(50.0),
                         ..default()
                     },
-                    RaycastPickCamera::default(),
                 ))
                 .insert(PlayerCameraMarker)
                 .id();
@@ -154,18 +160,28 @@ pub fn spawn_rigged_entity(
                     .expect("It is very difficult for this error to occur. Stop messing with the code");
             }
             debug!("Here");
-            commands.entity(spawned_entity).insert(Player::new(
+            let mut player = Player::new(
                 deserialised.respawn_point.coord.q,
                 deserialised.respawn_point.coord.r,
-                re_map.0.get(&event.entity_type).unwrap().animations[9].duration,
                 deserialised.stats.to_tupple(),
-            ));
-            commands.entity(spawned_entity).insert(Transform::from_xyz(
-                deserialised.hex_coord.q as f32 * HORIZONTAL_SPACING
-                    + deserialised.hex_coord.r as f32 % 2.0 * HOR_OFFSET,
-                1.0,
-                deserialised.hex_coord.r as f32 * VERTICAL_SPACING,
-            ));
+            );
+            player.waypoints = std::mem::take(&mut deserialised.waypoints);
+            player.abilities = std::mem::take(&mut deserialised.abilities);
+            // A step takes as long as the running animation, which is pended for every step
+            let run_duration = re_map.0.get(&event.entity_type).unwrap().animations[9].duration;
+            commands.entity(spawned_entity).insert(
+                HexMover::new(player.hex_coord, run_duration, 1.0)
+                    .with_run_animation(9)
+                    .with_speed(PLAYER_MOVEMENT_SPEED),
+            );
+            let combatant = Combatant::new(Faction::Player, Controller::Player, player.stats.speed);
+            commands
+                .entity(spawned_entity)
+                .insert((player, StatusEffects::default(), combatant));
+            let position = deserialised.hex_coord.to_world();
+            commands
+                .entity(spawned_entity)
+                .insert(Transform::from_xyz(position.x, 1.0, position.y));
         }
     }
 }
@@ -198,7 +214,9 @@ pub fn background_animation(
                 let target_anim =
                     &re_map.0.get(re_type).unwrap().animations[rentity.current_animation.as_ref().unwrap().index];
 
-                anim_player.start_with_transition(target_anim.handle.clone_weak(), Duration::from_secs_f32(0.5));
+                anim_player
+                    .start_with_transition(target_anim.handle.clone_weak(), Duration::from_secs_f32(0.5))
+                    .set_speed(rentity.pending_speed);
             }
 
             // 100% safe to unwrap
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use crate::*;
 use serde::{Deserialize, Serialize};
 
@@ -8,10 +10,23 @@ pub struct Tile {
     pub is_obstructed: bool,
     pub can_be_clicked: bool,
     pub sub_region_id: Option<SubregionData>,
+    /// Height of the tile in steps of [`ELEVATION_STEP`]
+    #[serde(default)]
+    pub elevation: i32,
+    /// Optional decoration drawn on top of the tile, e.g. a tree on an obstructed tile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prop: Option<TileProp>,
+    /// Whether the player can fast travel to and from this tile once it has been visited
+    #[serde(default)]
+    pub is_waypoint: bool,
+    /// Whether the player has ever seen this tile. Persisted to world.json so exploration survives reloads
+    #[serde(default)]
+    pub is_explored: bool,
     #[serde(default, skip_serializing)]
     pub is_hovered: bool,
+    /// Whether the tile is currently within the player's sight radius
     #[serde(default, skip_serializing)]
-    pub is_clicked: bool,
+    pub is_visible: bool,
 }
 
 impl Tile {
@@ -21,13 +36,17 @@ impl Tile {
             is_obstructed,
             can_be_clicked: false,
             sub_region_id,
+            elevation: 0,
+            prop: None,
+            is_waypoint: false,
+            is_explored: false,
             is_hovered: false,
-            is_clicked: false,
+            is_visible: false,
         }
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Reflect, Default, Serialize, Deserialize, FromReflect)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
 pub struct HexCoord {
     pub q: i32,
     pub r: i32,
@@ -43,6 +62,31 @@ impl HexCoord {
     pub fn to_tupple(&self) -> (i32, i32) {
         (self.q, self.r)
     }
+
+    /// Position of the centre of the tile on the xz plane, ignoring elevation
+    pub fn to_world(&self) -> Vec2 {
+        Vec2::new(
+            self.q as f32 * HORIZONTAL_SPACING + self.r as f32 % 2.0 * HOR_OFFSET,
+            self.r as f32 * VERTICAL_SPACING,
+        )
+    }
+
+    /// The tile containing a point on the xz plane.
+    ///
+    /// Tiles are regular hexagons, so the closest tile centre is always the tile the point lies in. Only the rows
+    /// either side of the nearest one can have a closer centre.
+    pub fn world_to_hex(x: f32, z: f32) -> HexCoord {
+        let point = Vec2::new(x, z);
+        let nearest_row = (z / VERTICAL_SPACING).round() as i32;
+
+        (nearest_row - 1..=nearest_row + 1)
+            .map(|r| {
+                let q = ((x - r as f32 % 2.0 * HOR_OFFSET) / HORIZONTAL_SPACING).round() as i32;
+                HexCoord::new(q, r)
+            })
+            .min_by(|a, b| a.to_world().distance(point).total_cmp(&b.to_world().distance(point)))
+            .unwrap()
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -72,6 +116,85 @@ fn offset2axial(a: &HexCoord) -> HexCoord {
     HexCoord::new(q, r)
 }
 
+fn axial2offset(a: &HexCoord) -> HexCoord {
+    let q = a.q + (a.r - (a.r & 1)) / 2;
+    let r = a.r;
+    HexCoord::new(q, r)
+}
+
+/// Rounds fractional axial coordinates to the nearest hex, by rounding in cube space and fixing up the
+/// component with the largest rounding error
+fn axial_round(q: f32, r: f32) -> HexCoord {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+
+    HexCoord::new(rq as i32, rr as i32)
+}
+
+/// Every hex crossed by a straight line from `a` to `b`, inclusive of both ends
+pub fn hex_line(a: &HexCoord, b: &HexCoord) -> Vec<HexCoord> {
+    let n = hex_distance(a, b);
+    let a_ax = offset2axial(a);
+    let b_ax = offset2axial(b);
+
+    // Nudge the start point so lines running exactly along hex edges don't flip-flop between neighbours
+    let (aq, ar) = (a_ax.q as f32 + 1e-6, a_ax.r as f32 + 1e-6);
+    let (bq, br) = (b_ax.q as f32, b_ax.r as f32);
+
+    (0..=n)
+        .map(|i| {
+            let t = if n == 0 { 0.0 } else { i as f32 / n as f32 };
+            axial2offset(&axial_round(aq + (bq - aq) * t, ar + (br - ar) * t))
+        })
+        .collect()
+}
+
+/// The six hexes `distance` away from `origin` in a straight line, one along each direction
+pub fn straight_line_tiles(origin: &HexCoord, distance: i32) -> Vec<HexCoord> {
+    let origin = offset2axial(origin);
+    [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)]
+        .iter()
+        .map(|(dq, dr)| axial2offset(&HexCoord::new(origin.q + dq * distance, origin.r + dr * distance)))
+        .collect()
+}
+
+/// All hexes within `radius` of `origin` that have line of sight to it.
+///
+/// A hex is blocked if an obstructed hex sits strictly between it and the origin. Obstructed hexes themselves
+/// can still be seen, so walls show up at the edge of the player's vision.
+pub fn field_of_view(origin: HexCoord, radius: i32, obstructed_tiles: &Vec<HexCoord>) -> Vec<HexCoord> {
+    let mut visible = vec![];
+
+    for r in origin.r - radius..=origin.r + radius {
+        for q in origin.q - radius..=origin.q + radius {
+            let target = HexCoord::new(q, r);
+            if hex_distance(&origin, &target) > radius {
+                continue;
+            }
+
+            let line = hex_line(&origin, &target);
+            let blocked = line
+                .iter()
+                .skip(1)
+                .take(line.len().saturating_sub(2))
+                .any(|&coord| check_obstructed(obstructed_tiles, coord));
+
+            if !blocked {
+                visible.push(target);
+            }
+        }
+    }
+
+    visible
+}
+
 pub fn hex_distance(a: &HexCoord, b: &HexCoord) -> i32 {
     let a = offset2axial(a);
     let b = offset2axial(b);
@@ -94,11 +217,59 @@ fn reconstruct_path(came_from: HashMap<HexCoord, HexCoord>, mut current: HexCoor
     path
 }
 
+fn elevation_of(elevations: &HashMap<HexCoord, i32>, coord: HexCoord) -> i32 {
+    *elevations.get(&coord).unwrap_or(&0)
+}
+
+/// The movement cost of stepping from a tile at elevation `from` to a neighbouring tile at elevation `to`.
+///
+/// Climbing costs [`CLIMB_COST`] extra per step of height, descending is free, and `None` means the height
+/// difference is larger than [`MAX_CLIMB`] so the step can't be made at all.
+pub fn step_cost(from: i32, to: i32) -> Option<i32> {
+    if (to - from).abs() > MAX_CLIMB {
+        return None;
+    }
+    Some(1 + (to - from).max(0) * CLIMB_COST)
+}
+
+/// Shortens a path (not including the start tile) to the steps that can be afforded with `budget` movement.
+/// Returns the movement spent
+pub fn truncate_path_to_budget(
+    path: &mut Vec<HexCoord>,
+    start: HexCoord,
+    budget: i32,
+    elevations: &HashMap<HexCoord, i32>,
+) -> i32 {
+    let mut spent = 0;
+    let mut previous = start;
+    let mut affordable = 0;
+
+    for &coord in path.iter() {
+        let cost = step_cost(elevation_of(elevations, previous), elevation_of(elevations, coord)).unwrap_or(i32::MAX);
+        if spent + cost > budget {
+            break;
+        }
+        spent += cost;
+        affordable += 1;
+        previous = coord;
+    }
+
+    path.truncate(affordable);
+    spent
+}
+
 fn check_obstructed(obstructed_tiles: &Vec<HexCoord>, current_coord: HexCoord) -> bool {
     obstructed_tiles.iter().find(|&&coord| coord == current_coord).is_some()
 }
 
-pub fn astar(start: HexCoord, goal: HexCoord, obstructed_tiles: &Vec<HexCoord>) -> Option<Vec<HexCoord>> {
+pub fn astar(
+    start: HexCoord,
+    goal: HexCoord,
+    obstructed_tiles: &Vec<HexCoord>,
+    elevations: &HashMap<HexCoord, i32>,
+) -> Option<Vec<HexCoord>> {
+    // Regions can have tens of thousands of tiles, so avoid searching through the list for every neighbour
+    let obstructed_tiles: HashSet<HexCoord> = obstructed_tiles.iter().copied().collect();
     let mut open_set = BinaryHeap::new();
     let mut came_from: HashMap<HexCoord, HexCoord> = HashMap::new();
     let mut g_score: HashMap<HexCoord, i32> = HashMap::new();
@@ -119,10 +290,13 @@ pub fn astar(start: HexCoord, goal: HexCoord, obstructed_tiles: &Vec<HexCoord>)
         }
 
         for neighbor in get_neighbors(&current) {
-            if check_obstructed(obstructed_tiles, current) {
+            if obstructed_tiles.contains(&current) {
                 continue;
             }
-            let tentative_g_score = g_score[&current] + 1;
+            let Some(cost) = step_cost(elevation_of(elevations, current), elevation_of(elevations, neighbor)) else {
+                continue;
+            };
+            let tentative_g_score = g_score[&current] + cost;
 
             if !g_score.contains_key(&neighbor) || tentative_g_score < g_score[&neighbor] {
                 came_from.insert(neighbor, current);
@@ -130,7 +304,7 @@ pub fn astar(start: HexCoord, goal: HexCoord, obstructed_tiles: &Vec<HexCoord>)
                 let f_score = tentative_g_score + hex_distance(&neighbor, &goal);
                 open_set.push(HexNode {
                     coord: neighbor,
-                    is_obstructed: check_obstructed(obstructed_tiles, current),
+                    is_obstructed: obstructed_tiles.contains(&current),
                     g_score: tentative_g_score,
                     f_score,
                     parent: Some(current),
@@ -166,3 +340,61 @@ pub fn get_neighbors(coord: &HexCoord) -> Vec<HexCoord> {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_to_hex_finds_tile_centres() {
+        for r in -3..=12 {
+            for q in -3..=12 {
+                let x = q as f32 * HORIZONTAL_SPACING + r as f32 % 2.0 * HOR_OFFSET;
+                let z = r as f32 * VERTICAL_SPACING;
+                assert_eq!(HexCoord::world_to_hex(x, z), HexCoord::new(q, r));
+            }
+        }
+    }
+
+    #[test]
+    fn world_to_hex_handles_points_near_tile_edges() {
+        let centre = HexCoord::new(4, 5).to_world();
+
+        // Just inside the flat left and right sides of the tile
+        let inset = HORIZONTAL_SPACING / 2.0 - 0.05;
+        assert_eq!(HexCoord::world_to_hex(centre.x + inset, centre.y), HexCoord::new(4, 5));
+        assert_eq!(HexCoord::world_to_hex(centre.x - inset, centre.y), HexCoord::new(4, 5));
+
+        // Just past them, in the neighbours on the same row
+        let outset = HORIZONTAL_SPACING / 2.0 + 0.05;
+        assert_eq!(HexCoord::world_to_hex(centre.x + outset, centre.y), HexCoord::new(5, 5));
+        assert_eq!(HexCoord::world_to_hex(centre.x - outset, centre.y), HexCoord::new(3, 5));
+    }
+
+    #[test]
+    fn world_to_hex_picks_rows_by_offset() {
+        // Points between rows belong to whichever of the staggered tiles they are closest to
+        let odd = HexCoord::new(2, 1).to_world();
+        assert_eq!(
+            HexCoord::world_to_hex(odd.x, odd.y - VERTICAL_SPACING * 0.6),
+            HexCoord::new(2, 1)
+        );
+        assert_eq!(
+            HexCoord::world_to_hex(odd.x + HOR_OFFSET, odd.y - VERTICAL_SPACING),
+            HexCoord::new(3, 0)
+        );
+        assert_eq!(
+            HexCoord::world_to_hex(odd.x - HOR_OFFSET, odd.y + VERTICAL_SPACING),
+            HexCoord::new(2, 2)
+        );
+    }
+
+    #[test]
+    fn world_to_hex_round_trips_neighbours() {
+        let coord = HexCoord::new(7, 8);
+        for neighbour in get_neighbors(&coord) {
+            let world = neighbour.to_world();
+            assert_eq!(HexCoord::world_to_hex(world.x, world.y), neighbour);
+        }
+    }
+}
@@ -13,7 +13,7 @@ impl Plugin for CharacterCreationPlugin {
     }
 }
 
-#[derive(Component, Reflect, Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub struct Stats {
     pub speed: i32,
     pub damage: i32,
@@ -24,17 +24,39 @@ impl Stats {
     pub fn to_tupple(&self) -> (i32, i32, i32) {
         (self.speed, self.damage, self.health)
     }
+
+    pub fn get(&self, stat: Stat) -> i32 {
+        match stat {
+            Stat::Speed => self.speed,
+            Stat::Damage => self.damage,
+            Stat::Health => self.health,
+        }
+    }
+
+    pub fn get_mut(&mut self, stat: Stat) -> &mut i32 {
+        match stat {
+            Stat::Speed => &mut self.speed,
+            Stat::Damage => &mut self.damage,
+            Stat::Health => &mut self.health,
+        }
+    }
+
+    /// Max HP that comes with the health stat
+    pub fn max_hp(&self) -> f32 {
+        ((self.health + 5) * 10) as f32
+    }
 }
 
 #[derive(Component)]
 struct CharacterCreationUI;
+/// Adds `modification_value` to a stat. Also used to spend stat points, see [`LevellingPlugin`]
 #[derive(Component)]
-struct ModifyStatButton {
-    modification_value: i32,
-    stat_type: Stat,
+pub struct ModifyStatButton {
+    pub modification_value: i32,
+    pub stat_type: Stat,
 }
 #[derive(Component, Debug, Clone, Copy)]
-enum Stat {
+pub enum Stat {
     Speed,
     Damage,
     Health,
@@ -98,7 +120,8 @@ fn add_character_creation_ui(mut commands: Commands, asset_server: Res<AssetServ
         });
 }
 
-fn create_stat_ui(parent: &mut ChildBuilder, asset_server: &Res<AssetServer>, stat_type: Stat) {
+/// A stat's name and value between buttons to take one off it and add one to it
+pub fn create_stat_ui(parent: &mut ChildBuilder, asset_server: &Res<AssetServer>, stat_type: Stat) {
     parent
         .spawn(NodeBundle {
             style: Style {
@@ -256,7 +279,7 @@ fn remove_character_creation_ui(
 ) {
     let current_player = fs::read_to_string("player_data.json").expect("Something went wrong reading the file");
     let mut deserialised: Player = serde_json::from_str(&current_player).unwrap();
-    deserialised.stats = stats_query.single().clone();
+    deserialised.set_stats(stats_query.single().clone());
     for entity in ui_query.iter() {
         commands.entity(entity).despawn_recursive();
     }
@@ -33,7 +33,6 @@ pub fn on_chest_open(
     mut next_ui_state: ResMut<NextState<UIState>>,
     mut gi_lock_sender: EventWriter<GlobalInteractionLockEvent>,
     mut map_ctx: ResMut<MapContext>,
-    mut player: Query<&mut Player>,
     primary_window: Query<&Window, With<PrimaryWindow>>,
     asset_server: Res<AssetServer>,
     mut inventory: ResMut<Inventory>,
@@ -42,8 +41,6 @@ pub fn on_chest_open(
         next_ui_state.set(UIState::OpenChest);
         gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Locked));
 
-        player.get_single_mut().unwrap().path = None;
-
         commands.entity(event.chest_ent).despawn_recursive();
 
         let chest = &event.chest;
@@ -0,0 +1,236 @@
+use crate::*;
+
+/// Plugin responsible for streaming a region's tiles in and out around the camera.
+///
+/// [`update_world`] hands the tiles of a newly loaded region to [`RegionChunks`]. They are then spawned a few chunks
+/// per frame, nearest to the camera first, while a loading indicator is shown. Chunks the camera moves far away from
+/// are despawned, and their tiles kept so they can be spawned again later.
+pub struct ChunkPlugin;
+
+impl Plugin for ChunkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RegionChunks>()
+            .add_system(stream_chunks.after(update_world))
+            .add_system(update_chunk_loading_indicator.after(stream_chunks));
+    }
+}
+
+/// Width and height of a chunk, in tiles
+pub const CHUNK_SIZE: i32 = 16;
+/// Chunks with a centre closer than this to the camera are spawned
+pub const CHUNK_SPAWN_DISTANCE: f32 = 90.0;
+/// Chunks with a centre further than this from the camera are despawned. Larger than [`CHUNK_SPAWN_DISTANCE`] so
+/// chunks on the edge don't flicker in and out as the camera moves
+pub const CHUNK_DESPAWN_DISTANCE: f32 = 120.0;
+/// Spreads region loading across frames
+const CHUNKS_SPAWNED_PER_FRAME: usize = 2;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ChunkCoord {
+    pub fn of(coord: &HexCoord) -> ChunkCoord {
+        ChunkCoord {
+            x: coord.q.div_euclid(CHUNK_SIZE),
+            y: coord.r.div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// World space position of the middle of the chunk, on the xz plane
+    pub fn centre(&self) -> Vec2 {
+        let half = CHUNK_SIZE as f32 / 2.0;
+        Vec2::new(
+            (self.x as f32 * CHUNK_SIZE as f32 + half) * HORIZONTAL_SPACING,
+            (self.y as f32 * CHUNK_SIZE as f32 + half) * VERTICAL_SPACING,
+        )
+    }
+}
+
+/// Parent of every tile entity in a chunk
+#[derive(Component)]
+pub struct Chunk(pub ChunkCoord);
+
+/// Every tile of the current region, grouped into chunks
+#[derive(Resource, Default)]
+pub struct RegionChunks {
+    /// Tiles of the chunks that aren't spawned. Tiles of spawned chunks live on their entities instead
+    stored: HashMap<ChunkCoord, Vec<Tile>>,
+    spawned: HashMap<ChunkCoord, Entity>,
+    /// Terrain of the whole region, which pathfinding needs regardless of what's spawned
+    obstructed: Vec<HexCoord>,
+    elevations: HashMap<HexCoord, i32>,
+    max_elevation: i32,
+    /// Whether chunks near the camera are still waiting to be spawned
+    pub loading: bool,
+}
+
+impl RegionChunks {
+    pub fn new(tiles: Vec<Tile>) -> RegionChunks {
+        let obstructed = tiles.iter().filter(|t| t.is_obstructed).map(|t| t.coord).collect();
+        let elevations = tiles.iter().map(|t| (t.coord, t.elevation)).collect();
+        let max_elevation = tiles.iter().map(|t| t.elevation).max().unwrap_or(0);
+
+        let mut stored: HashMap<ChunkCoord, Vec<Tile>> = HashMap::new();
+        for tile in tiles {
+            stored.entry(ChunkCoord::of(&tile.coord)).or_default().push(tile);
+        }
+
+        RegionChunks {
+            stored,
+            spawned: HashMap::new(),
+            obstructed,
+            elevations,
+            max_elevation,
+            loading: true,
+        }
+    }
+
+    /// Obstructed tiles of the whole region, spawned or not
+    pub fn obstructed_tiles(&self) -> Vec<HexCoord> {
+        self.obstructed.clone()
+    }
+
+    /// Elevations of the whole region, spawned or not
+    pub fn elevations(&self) -> &HashMap<HexCoord, i32> {
+        &self.elevations
+    }
+
+    /// Elevation of the highest tile in the region
+    pub fn max_elevation(&self) -> i32 {
+        self.max_elevation
+    }
+}
+
+pub fn stream_chunks(
+    mut commands: Commands,
+    mut region_chunks: ResMut<RegionChunks>,
+    camera: Query<&GlobalTransform, With<PlayerCameraMarker>>,
+    tiles: Query<(&Tile, &Parent)>,
+    tile_assets: Res<TileAssets>,
+    prop_palette: Res<PropPalette>,
+    asset_server: Res<AssetServer>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let camera_position = Vec2::new(camera.translation().x, camera.translation().z);
+
+    // Despawn far away chunks, putting their tiles back into storage as they are now, e.g. with exploration updated
+    let far_chunks: Vec<(ChunkCoord, Entity)> = region_chunks
+        .spawned
+        .iter()
+        .filter(|(chunk, _)| chunk.centre().distance(camera_position) > CHUNK_DESPAWN_DISTANCE)
+        .map(|(chunk, entity)| (*chunk, *entity))
+        .collect();
+    for (chunk, entity) in far_chunks {
+        let chunk_tiles = tiles
+            .iter()
+            .filter(|(_, parent)| parent.get() == entity)
+            .map(|(tile, _)| Tile {
+                is_hovered: false,
+                ..tile.clone()
+            })
+            .collect();
+        region_chunks.spawned.remove(&chunk);
+        region_chunks.stored.insert(chunk, chunk_tiles);
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let mut nearby_chunks: Vec<(ChunkCoord, f32)> = region_chunks
+        .stored
+        .keys()
+        .map(|chunk| (*chunk, chunk.centre().distance(camera_position)))
+        .filter(|(_, distance)| *distance <= CHUNK_SPAWN_DISTANCE)
+        .collect();
+    nearby_chunks.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    for (chunk, _) in nearby_chunks.iter().take(CHUNKS_SPAWNED_PER_FRAME) {
+        let chunk_tiles = region_chunks.stored.remove(chunk).unwrap();
+        let entity = commands
+            .spawn(SpatialBundle::default())
+            .insert(Name::new(format!("Chunk ({}, {})", chunk.x, chunk.y)))
+            .insert(Chunk(*chunk))
+            .with_children(|parent| {
+                for tile in chunk_tiles {
+                    spawn_tile(parent, tile, &tile_assets, &prop_palette, &asset_server);
+                }
+            })
+            .id();
+        region_chunks.spawned.insert(*chunk, entity);
+    }
+
+    region_chunks.loading = nearby_chunks.len() > CHUNKS_SPAWNED_PER_FRAME;
+}
+
+#[derive(Component)]
+struct ChunkLoadingIndicator;
+
+fn update_chunk_loading_indicator(
+    mut commands: Commands,
+    region_chunks: Res<RegionChunks>,
+    indicator: Query<Entity, With<ChunkLoadingIndicator>>,
+    asset_server: Res<AssetServer>,
+) {
+    match (region_chunks.loading, indicator.get_single()) {
+        (true, Err(_)) => {
+            commands
+                .spawn(
+                    TextBundle::from_section(
+                        "Loading region...",
+                        TextStyle {
+                            font: asset_server.load("font.otf"),
+                            font_size: 30.0,
+                            color: Color::WHITE,
+                        },
+                    )
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            bottom: Val::Px(20.0),
+                            right: Val::Px(20.0),
+                            ..default()
+                        },
+                        ..default()
+                    }),
+                )
+                .insert(Name::new("Chunk loading indicator"))
+                .insert(ChunkLoadingIndicator);
+        }
+        (false, Ok(entity)) => commands.entity(entity).despawn_recursive(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_regions_are_split_into_chunks() {
+        let size = 200;
+        let tiles = (0..size)
+            .flat_map(|r| (0..size).map(move |q| Tile::new(q, r, q == r, None)))
+            .collect();
+        let region = Region {
+            tiles,
+            ..Default::default()
+        };
+        // Loaded the way update_world does, from the JSON of world.json
+        let world = serde_json::to_string(&HashMap::from([("1", region)])).unwrap();
+        let mut world: HashMap<String, Region> = serde_json::from_str(&world).unwrap();
+        let chunks = RegionChunks::new(world.remove("1").unwrap().tiles);
+
+        let chunks_across = (size + CHUNK_SIZE - 1) / CHUNK_SIZE;
+        assert_eq!(chunks.stored.len(), (chunks_across * chunks_across) as usize);
+        assert!(chunks
+            .stored
+            .values()
+            .all(|tiles| tiles.len() <= (CHUNK_SIZE * CHUNK_SIZE) as usize));
+        assert_eq!(chunks.stored[&ChunkCoord { x: 12, y: 12 }].len(), 8 * 8);
+        assert_eq!(chunks.elevations().len(), (size * size) as usize);
+        assert_eq!(chunks.obstructed_tiles().len(), size as usize);
+    }
+}
@@ -1,17 +1,36 @@
+use bevy::ecs::component::TableStorage;
+use bevy::reflect::{impl_from_reflect_value, impl_reflect_struct, impl_reflect_value};
+use rand::{prelude::*, rngs::StdRng};
+
 use crate::*;
 
+/// Plugin responsible for fights, played out by the rules of [`CombatState`].
+///
+/// Clicks, buttons and [`CombatState::ai_action`] are turned into [`CombatAction`]s, which [`apply_combat_actions`]
+/// carries out. The [`CombatEvent`]s that come out of them are turned into movement, animations and UI by
+/// [`present_combat_events`], which also copies the state of every unit back to its entity.
 pub struct CombatPlugin;
 
 impl Plugin for CombatPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(add_combat_stuff.run_if(resource_added::<CombatManager>()))
+        app.init_resource::<CombatRng>()
+            .add_event::<CombatAction>()
+            .add_event::<CombatEvent>()
+            .add_systems((add_combat_stuff, start_combat).distributive_run_if(resource_added::<CombatManager>()))
             .add_systems(
                 (
                     combat_system,
                     combat_button_system.in_set(OnUpdate(UIState::Null)),
-                    enemy_ai,
-                    update_enemy_health,
-                    update_player_health,
+                    keyboard_undo_moves.in_set(OnUpdate(UIState::Null)),
+                    enemy_ai.after(start_moves),
+                    apply_combat_actions
+                        .after(combat_system)
+                        .after(combat_button_system)
+                        .after(keyboard_undo_moves)
+                        .after(enemy_ai),
+                    present_combat_events.after(apply_combat_actions),
+                    update_enemy_health.after(present_combat_events),
+                    update_player_health.after(present_combat_events),
                     button_reset_system,
                     update_combat_information,
                 )
@@ -24,31 +43,99 @@ impl Plugin for CombatPlugin {
 #[derive(Component, Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
 pub struct Enemy {
     pub hex_coord: HexCoord,
-    #[serde(default, skip_serializing)]
-    pub path: Option<Vec<HexCoord>>,
     pub attack_range: i32,
     pub movement_range: i32,
     pub damage: f32,
     pub health: Health,
-    #[serde(default, skip)]
-    pub ended_turn: bool,
-    #[serde(default, skip)]
-    pub move_timer: Timer,
+    #[serde(default)]
+    pub damage_stats: DamageStats,
 }
 
 impl Enemy {
     pub fn new(q: i32, r: i32, attack_range: i32, movement_range: i32, damage: f32, hp: f32) -> Enemy {
         Enemy {
             hex_coord: HexCoord::new(q, r),
-            path: None,
-            move_timer: Timer::from_seconds(0.5, TimerMode::Repeating),
             attack_range,
             movement_range,
             damage,
             health: Health::new(hp),
-            ended_turn: false,
+            damage_stats: DamageStats::default(),
         }
     }
+
+    pub fn with_damage_stats(mut self, damage_stats: DamageStats) -> Enemy {
+        self.damage_stats = damage_stats;
+        self
+    }
+}
+
+// The combat core doesn't know about Bevy, so the types it shares with the rest of the game get their Bevy traits
+// here instead of deriving them
+
+impl_reflect_struct!(
+    #[reflect(Debug, PartialEq, Hash, Default)]
+    struct HexCoord {
+        q: i32,
+        r: i32,
+    }
+);
+
+impl_reflect_struct!(
+    #[reflect(Debug)]
+    struct Health {
+        max_hp: f32,
+        hp: f32,
+    }
+);
+
+impl_reflect_struct!(
+    #[reflect(Debug, Default)]
+    struct Stats {
+        speed: i32,
+        damage: i32,
+        health: i32,
+    }
+);
+
+impl Component for Stats {
+    type Storage = TableStorage;
+}
+
+impl_reflect_value!(Element(Debug, PartialEq, Hash, Default));
+impl_from_reflect_value!(Element);
+
+impl_reflect_struct!(
+    #[reflect(Debug, PartialEq, Default)]
+    struct DamageStats {
+        accuracy: f32,
+        evasion: f32,
+        crit_chance: f32,
+        crit_multiplier: f32,
+        armor: f32,
+        resistances: HashMap<Element, f32>,
+    }
+);
+
+impl Component for StatusEffects {
+    type Storage = TableStorage;
+}
+
+/// Seeds the rolls of every fight. Seeded once per run, and the seed logged, so a run's fights can be reproduced with
+/// [`CombatRng::new`]. Each fight's own seed ends up in its [`CombatLog`]
+#[derive(Resource)]
+pub struct CombatRng(pub StdRng);
+
+impl CombatRng {
+    pub fn new(seed: u64) -> CombatRng {
+        info!("Combat seed: {}", seed);
+        CombatRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl Default for CombatRng {
+    fn default() -> Self {
+        CombatRng::new(rand::random())
+    }
 }
 #[derive(Reflect, FromReflect, Serialize, Deserialize)]
 pub struct RespawnPoint {
@@ -56,181 +143,187 @@ pub struct RespawnPoint {
     pub coord: HexCoord,
 }
 
-#[derive(Resource, PartialEq, Reflect, Debug)]
+#[derive(Resource)]
 pub struct CombatManager {
-    pub turn: Turn,
+    pub state: CombatState,
+    /// The entity of every unit in the fight, indexed by [`UnitId`]
+    pub units: Vec<Entity>,
     pub player_action: Option<AcitonType>,
     pub reset_buttons: bool,
+    /// The unit still walking the path of its last move. It can't set off on another one until it has arrived, or its
+    /// entity would end up somewhere other than the unit
+    pub walking: Option<UnitId>,
 }
 
 impl CombatManager {
+    /// The fight is set up by [`start_combat`] once everyone taking part has been spawned
     pub fn new() -> CombatManager {
         CombatManager {
-            turn: Turn::Player(Phase::Movement),
+            state: CombatState::new(CombatGrid::default(), vec![], 0),
+            units: vec![],
             player_action: None,
             reset_buttons: false,
+            walking: None,
         }
     }
-}
 
-#[derive(PartialEq, Reflect, FromReflect, Clone, Debug)]
-pub enum Turn {
-    Player(Phase),
-    // Allies,
-    Enemies,
+    /// Carries out an action, see [`CombatState::apply`]. Moves are ignored while the last one is still being walked
+    pub fn apply(&mut self, action: CombatAction) -> Vec<CombatEvent> {
+        if matches!(action, CombatAction::Move(_)) && self.walking.is_some() {
+            return vec![];
+        }
+        let events = self.state.apply(action);
+        for event in &events {
+            match event {
+                CombatEvent::Moved { unit, .. } => self.walking = Some(*unit),
+                CombatEvent::MoveUndone { .. } => self.walking = None,
+                _ => {}
+            }
+        }
+        events
+    }
+
+    /// Lets the unit that was walking move again once its entity has arrived
+    pub fn walk_finished(&mut self, entity: Entity) {
+        if self.walking.is_some_and(|unit| self.entity(unit) == entity) {
+            self.walking = None;
+        }
+    }
+
+    pub fn entity(&self, unit: UnitId) -> Entity {
+        self.units[unit.0]
+    }
+
+    pub fn unit_of(&self, entity: Entity) -> Option<UnitId> {
+        self.units.iter().position(|&e| e == entity).map(UnitId)
+    }
+
+    /// The unit whose turn it is, if it's one the player controls
+    pub fn player_unit(&self) -> Option<&CombatUnit> {
+        let unit = self.state.unit(self.state.current()?);
+        (unit.controller == Controller::Player).then_some(unit)
+    }
+
+    /// The ability the player has selected, if any
+    pub fn selected_ability(&self) -> Option<&Ability> {
+        match self.player_action {
+            Some(AcitonType::Ability(index)) => self.player_unit()?.abilities.get(index),
+            _ => None,
+        }
+    }
 }
 
-#[derive(PartialEq, Reflect, FromReflect, Clone, Debug)]
-pub enum Phase {
-    Movement,
-    Action1,
-    Action2,
+/// Sets the fight up once everyone taking part in it has been spawned, and hands out the first turn
+#[allow(clippy::type_complexity)]
+pub fn start_combat(
+    mut combat_manager: ResMut<CombatManager>,
+    units: Query<(Entity, &Combatant, &StatusEffects, Option<&Player>, Option<&Enemy>)>,
+    region_chunks: Res<RegionChunks>,
+    ability_book: Res<AbilityBook>,
+    mut combat_rng: ResMut<CombatRng>,
+    mut combat_events: EventWriter<CombatEvent>,
+) {
+    let mut entities = vec![];
+    let mut combat_units = vec![];
+    for (entity, combatant, effects, player, enemy) in &units {
+        let unit = if let Some(player) = player {
+            let abilities = ability_book.player_abilities(player).cloned().collect();
+            // The player's initiative is their speed, which keeps up with them getting faster
+            CombatUnit::new(
+                combatant.faction,
+                combatant.controller,
+                player.hex_coord,
+                player.health.clone(),
+                player.stats.speed,
+            )
+            .with_abilities(player.stats.clone(), player.energy, abilities)
+            .with_damage_stats(player.damage_stats.clone())
+        } else if let Some(enemy) = enemy {
+            CombatUnit::new(
+                combatant.faction,
+                combatant.controller,
+                enemy.hex_coord,
+                enemy.health.clone(),
+                enemy.movement_range,
+            )
+            .with_initiative(combatant.initiative)
+            .with_attack(Attack {
+                range: enemy.attack_range,
+                damage: enemy.damage,
+            })
+            .with_damage_stats(enemy.damage_stats.clone())
+        } else {
+            continue;
+        };
+        entities.push(entity);
+        combat_units.push(unit.with_effects(effects.0.clone()));
+    }
+
+    let grid = CombatGrid::new(region_chunks.elevations().clone(), region_chunks.obstructed_tiles());
+    combat_manager.state = CombatState::new(grid, combat_units, combat_rng.0.gen());
+    combat_manager.units = entities;
+    combat_events.send_batch(combat_manager.state.start());
 }
 
+/// Previews where the selected ability can be used and what it would hit, and turns the player's clicks into actions:
+/// walking in the movement phase, or using the selected ability. Both are for the player controlled unit whose turn
+/// it is
 pub fn combat_system(
-    mut combat_manager: ResMut<CombatManager>,
-    mut tiles: Query<(&Handle<StandardMaterial>, &mut Tile)>,
-    mut spells: Query<(&mut Transform, &Spell)>,
-    mut enemies: Query<&mut Enemy>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut gi_lock_sender: EventWriter<GlobalInteractionLockEvent>,
-    mut player_query: Query<&mut Player>,
+    combat_manager: Res<CombatManager>,
+    mut tiles: Query<(&mut TileHighlight, &Tile)>,
+    enemies: Query<&Enemy>,
+    mut tile_clicks: EventReader<TileClicked>,
+    mut combat_actions: EventWriter<CombatAction>,
 ) {
-    // Response to player chosing action
-    let mut player = player_query.single_mut();
-    'outer: for (tile_mat, tile) in &mut tiles {
-        let mut raw_mat = materials.get_mut(tile_mat).unwrap();
-        if player.path.is_none() {
-            if !tile.is_obstructed {
-                raw_mat.base_color = Color::rgba(1.0, 1.0, 1.0, 0.6);
-            }
-            if tile.is_hovered {
-                for enemy in &enemies {
-                    if enemy.hex_coord == tile.coord {
-                        continue 'outer;
-                    }
-                }
-                raw_mat.base_color = Color::BLUE;
-            }
+    let user = combat_manager.player_unit();
+    let ability = combat_manager.selected_ability().zip(user.map(|unit| unit.coord));
+    // Preview what the selected ability would hit if it were used on the hovered tile
+    let hovered = tiles
+        .iter()
+        .find(|(_, tile)| tile.is_hovered)
+        .map(|(_, tile)| tile.coord);
+    let splash = match (ability, hovered) {
+        (Some((ability, from)), Some(target)) if ability.can_target(&from, &target) => {
+            ability.affected_tiles(&from, &target)
         }
+        _ => vec![],
+    };
+    // Everyone the ability would hurt: the other side, and the user's own side too if there's friendly fire
+    let friendly_fire = ability.is_some_and(|(ability, _)| ability.friendly_fire != FriendlyFire::Off);
+    let hit_coords: Vec<HexCoord> = user.map_or(vec![], |user| {
+        combat_manager
+            .state
+            .units
+            .iter()
+            .filter(|unit| unit.is_alive() && (unit.faction != user.faction || friendly_fire))
+            .map(|unit| unit.coord)
+            .collect()
+    });
+    for (mut highlight, tile) in &mut tiles {
+        let in_range =
+            !tile.is_obstructed && ability.is_some_and(|(ability, from)| ability.can_target(&from, &tile.coord));
+        set_highlight(&mut highlight, HighlightLayer::AbilityRange, in_range);
+        let enemy_on_tile = enemies.iter().any(|enemy| enemy.hex_coord == tile.coord);
+        set_highlight(&mut highlight, HighlightLayer::DangerZone, enemy_on_tile);
+        let in_splash = splash.contains(&tile.coord);
+        set_highlight(&mut highlight, HighlightLayer::Splash, in_splash);
+        set_highlight(
+            &mut highlight,
+            HighlightLayer::SplashHit,
+            in_splash && hit_coords.contains(&tile.coord),
+        );
     }
-    if let Some(player_action) = combat_manager.player_action {
-        match player_action {
-            AcitonType::Fireball => {
-                for (tile_mat, tile) in &mut tiles {
-                    let mut raw_mat = materials.get_mut(tile_mat).unwrap();
-                    if !tile.is_obstructed {
-                        raw_mat.base_color = Color::LIME_GREEN;
-                    }
-                }
-            }
-            AcitonType::Smack => {
-                let adjactent = get_neighbors(&player.hex_coord);
-                for (tile_mat, tile) in &mut tiles {
-                    let mut raw_mat = materials.get_mut(tile_mat).unwrap();
-                    if !tile.is_obstructed && adjactent.contains(&tile.coord) {
-                        raw_mat.base_color = Color::LIME_GREEN;
-                    }
-                }
-            }
-            AcitonType::RunSmack => {
-                let possible_tiles = vec![
-                    HexCoord::new(player.hex_coord.q + 2, player.hex_coord.r),
-                    HexCoord::new(player.hex_coord.q - 2, player.hex_coord.r),
-                    HexCoord::new(player.hex_coord.q + 1, player.hex_coord.r + 2),
-                    HexCoord::new(player.hex_coord.q + 1, player.hex_coord.r - 2),
-                    HexCoord::new(player.hex_coord.q - 1, player.hex_coord.r + 2),
-                    HexCoord::new(player.hex_coord.q - 1, player.hex_coord.r - 2),
-                ];
-                for (tile_mat, tile) in &mut tiles {
-                    let mut raw_mat = materials.get_mut(tile_mat).unwrap();
-                    if !tile.is_obstructed && possible_tiles.contains(&tile.coord) {
-                        raw_mat.base_color = Color::LIME_GREEN;
-                    }
-                }
+
+    for click in tile_clicks.iter() {
+        let target = click.coord;
+        match combat_manager.player_action {
+            Some(AcitonType::Ability(ability)) => combat_actions.send(CombatAction::UseAbility { ability, target }),
+            _ if combat_manager.state.turn == Turn::Player(Phase::Movement) => {
+                combat_actions.send(CombatAction::Move(target))
             }
             _ => {}
         }
     }
-    // Execute action
-    'outer: for (_, mut tile) in &mut tiles {
-        if tile.is_clicked {
-            if let Some(player_action) = combat_manager.player_action {
-                tile.is_clicked = false;
-                let mut action_complete = false;
-                match player_action {
-                    AcitonType::Fireball => {
-                        action_complete = true;
-                        for (mut pos, spell) in &mut spells {
-                            if *spell == Spell::Fireball {
-                                pos.translation.x =
-                                    tile.coord.q as f32 * HORIZONTAL_SPACING + tile.coord.r as f32 % 2.0 * HOR_OFFSET;
-                                pos.translation.z = tile.coord.r as f32 * VERTICAL_SPACING;
-                                for mut enemy in &mut enemies {
-                                    if enemy.hex_coord == tile.coord {
-                                        enemy.health.hp -= 10.0 * player.stats.damage as f32
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    AcitonType::Smack => {
-                        if !get_neighbors(&player.hex_coord).contains(&tile.coord) {
-                            continue 'outer;
-                        }
-                        action_complete = true;
-                        for mut enemy in &mut enemies {
-                            if enemy.hex_coord == tile.coord {
-                                enemy.health.hp -= (player.stats.damage * 2) as f32
-                            }
-                        }
-                    }
-                    AcitonType::RunSmack => {
-                        if !vec![
-                            HexCoord::new(player.hex_coord.q + 2, player.hex_coord.r),
-                            HexCoord::new(player.hex_coord.q - 2, player.hex_coord.r),
-                            HexCoord::new(player.hex_coord.q + 1, player.hex_coord.r + 2),
-                            HexCoord::new(player.hex_coord.q + 1, player.hex_coord.r - 2),
-                            HexCoord::new(player.hex_coord.q - 1, player.hex_coord.r + 2),
-                            HexCoord::new(player.hex_coord.q - 1, player.hex_coord.r - 2),
-                        ]
-                        .contains(&tile.coord)
-                        {
-                            continue 'outer;
-                        }
-                        action_complete = true;
-                        for mut enemy in &mut enemies {
-                            if enemy.hex_coord == tile.coord {
-                                enemy.health.hp -= (player.stats.damage * 5) as f32
-                            }
-                        }
-                    }
-                    _ => {}
-                }
-                if action_complete {
-                    combat_manager.reset_buttons = true;
-                    combat_manager.player_action = None;
-                    if combat_manager.turn == Turn::Player(Phase::Action1) {
-                        combat_manager.turn = Turn::Player(Phase::Action2)
-                    } else if combat_manager.turn == Turn::Player(Phase::Action2) {
-                        combat_manager.turn = Turn::Enemies;
-                        for mut enemy in &mut enemies {
-                            enemy.ended_turn = false;
-                        }
-                    }
-                }
-            }
-            if combat_manager.turn == Turn::Player(Phase::Movement) {
-                for enemy in &enemies {
-                    if enemy.hex_coord == tile.coord {
-                        tile.is_clicked = false;
-                        continue 'outer;
-                    }
-                }
-                gi_lock_sender.send(GlobalInteractionLockEvent(GIState::LockedByMovement));
-            }
-        }
-    }
 }
 
 pub fn button_reset_system(
@@ -261,17 +354,15 @@ pub fn combat_button_system(
     >,
     mut combat_manager: ResMut<CombatManager>,
     mut gi_lock_sender: EventWriter<GlobalInteractionLockEvent>,
-    mut enemies: Query<&mut Enemy>,
-    mut player_query: Query<&mut Player>,
+    mut combat_actions: EventWriter<CombatAction>,
     // mut text_query: Query<&mut Text>,
     // other_text_queery: Query<&ButtonText>,
     // mut tile_queery: Query<&mut Tile>,
 ) {
-    let mut player = player_query.single_mut();
     for (interaction, mut color, _children, button_type, mut toggle_state) in &mut interaction_query {
         // let msg = format!("{:#?}", button_type);
         // trace!(msg);
-        if let Turn::Player(ref mut phase) = combat_manager.turn.clone() {
+        if let Turn::Player(_) = combat_manager.state.turn {
             if let ButtonType::CombatButton(cb_type) = *button_type {
                 match interaction {
                     Interaction::Clicked => {
@@ -280,84 +371,33 @@ pub fn combat_button_system(
                             CombatButtonType::Action(action) => match action {
                                 AcitonType::EndPhase => {
                                     *color = PRESSED_BUTTON.into();
-                                    match phase {
-                                        Phase::Movement => {
-                                            combat_manager.turn = Turn::Player(Phase::Action1);
-                                            player.energy += player.remaining_speed;
-                                            gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Locked));
-                                            //
-                                        }
-                                        Phase::Action1 => {
-                                            combat_manager.turn = Turn::Player(Phase::Action2);
-                                            gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Locked));
-                                            //
-                                        }
-                                        Phase::Action2 => {
-                                            combat_manager.turn = Turn::Enemies;
-                                            for mut enemy in &mut enemies {
-                                                enemy.ended_turn = false;
-                                            }
-                                            gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Locked));
-                                            //
-                                        }
-                                    }
+                                    combat_actions.send(CombatAction::EndPhase);
                                 }
-                                AcitonType::Fireball => {
-                                    if combat_manager.turn == Turn::Player(Phase::Action1)
-                                        || combat_manager.turn == Turn::Player(Phase::Action2)
-                                    {
-                                        if player.energy >= 2 {
-                                            if let Some(mut toggle) = toggle_state {
-                                                toggle.is_on = !toggle.is_on;
-                                                if toggle.is_on {
-                                                    *color = PRESSED_BUTTON.into();
-                                                    combat_manager.player_action = Some(AcitonType::Fireball);
-                                                    player.energy -= 2;
-                                                    gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked))
-                                                } else {
-                                                    *color = HOVERED_BUTTON.into();
-                                                    combat_manager.player_action = None;
-                                                    player.energy += 2;
-                                                    gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Locked))
-                                                }
-                                            }
-                                        }
-                                    }
+                                AcitonType::UndoMove => {
+                                    *color = PRESSED_BUTTON.into();
+                                    combat_actions.send(CombatAction::UndoMove);
                                 }
-                                AcitonType::Smack => {
-                                    if combat_manager.turn == Turn::Player(Phase::Action1)
-                                        || combat_manager.turn == Turn::Player(Phase::Action2)
+                                AcitonType::Ability(index) => {
+                                    // The energy is spent once the ability is used
+                                    let Some(can_afford) = combat_manager.player_unit().and_then(|unit| {
+                                        let ability = unit.abilities.get(index)?;
+                                        Some(unit.energy >= ability.energy_cost)
+                                    }) else {
+                                        continue;
+                                    };
+                                    if combat_manager.state.turn == Turn::Player(Phase::Action1)
+                                        || combat_manager.state.turn == Turn::Player(Phase::Action2)
                                     {
                                         if let Some(mut toggle) = toggle_state {
-                                            toggle.is_on = !toggle.is_on;
-                                            if toggle.is_on {
-                                                *color = PRESSED_BUTTON.into();
-                                                combat_manager.player_action = Some(AcitonType::Smack);
-                                                gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked));
-                                            } else {
-                                                *color = HOVERED_BUTTON.into();
-                                                combat_manager.player_action = None;
-                                                gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Locked))
-                                            }
-                                        }
-                                    }
-                                }
-                                AcitonType::RunSmack => {
-                                    if combat_manager.turn == Turn::Player(Phase::Action1)
-                                        || combat_manager.turn == Turn::Player(Phase::Action2)
-                                    {
-                                        if player.energy >= 1 {
-                                            if let Some(mut toggle) = toggle_state {
+                                            if toggle.is_on || can_afford {
                                                 toggle.is_on = !toggle.is_on;
                                                 if toggle.is_on {
                                                     *color = PRESSED_BUTTON.into();
-                                                    combat_manager.player_action = Some(AcitonType::RunSmack);
-                                                    player.energy -= 1;
+                                                    combat_manager.player_action = Some(AcitonType::Ability(index));
                                                     gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked));
                                                 } else {
                                                     *color = HOVERED_BUTTON.into();
                                                     combat_manager.player_action = None;
-                                                    player.energy += 1;
                                                     gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Locked))
                                                 }
                                             }
@@ -411,114 +451,174 @@ pub fn update_player_health(
         map_context.change_map(player.respawn_point.world.clone());
         player.health.hp = player.health.max_hp;
         player.hex_coord = player.respawn_point.coord;
-        p_transform.translation.x =
-            player.hex_coord.q as f32 * HORIZONTAL_SPACING + player.hex_coord.r as f32 % 2.0 * HOR_OFFSET;
-        p_transform.translation.z = player.hex_coord.r as f32 * VERTICAL_SPACING;
+        let position = player.hex_coord.to_world();
+        p_transform.translation.x = position.x;
+        p_transform.translation.z = position.y;
+    }
+}
+
+/// Backspace takes back the player's last walk of the movement phase, and with shift held every walk of it
+pub fn keyboard_undo_moves(
+    keys: Res<Input<KeyCode>>,
+    combat_manager: Res<CombatManager>,
+    mut combat_actions: EventWriter<CombatAction>,
+) {
+    if !keys.just_pressed(KeyCode::Back) || combat_manager.state.turn != Turn::Player(Phase::Movement) {
+        return;
+    }
+    if keys.any_pressed([KeyCode::LShift, KeyCode::RShift]) {
+        combat_actions.send(CombatAction::UndoAllMoves);
+    } else {
+        combat_actions.send(CombatAction::UndoMove);
     }
 }
 
+/// Takes the turns of AI controlled units one action at a time, letting each walk finish before deciding on the next
 pub fn enemy_ai(
-    tiles: Query<&Tile, With<Tile>>,
-    mut enemies: Query<(&mut Transform, &mut Enemy)>,
-    mut player_query: Query<&mut Player>,
-    mut combat_manager: ResMut<CombatManager>,
-    time: Res<Time>,
-    mut gi_lock_sender: EventWriter<GlobalInteractionLockEvent>,
+    combat_manager: Res<CombatManager>,
+    movers: Query<&HexMover>,
+    mut combat_actions: EventWriter<CombatAction>,
 ) {
-    // TEMP WORKAROUND DEPRECATE LATER
-    let player = player_query.get_single_mut();
-    if player.is_err() {
+    if combat_manager.state.turn != Turn::Ai {
+        return;
+    }
+    let Some(unit) = combat_manager.state.current() else {
+        return;
+    };
+    if movers
+        .get(combat_manager.entity(unit))
+        .is_ok_and(|mover| mover.is_moving())
+    {
         return;
     }
-    let mut player = player.unwrap();
-
-    if combat_manager.turn == Turn::Enemies {
-        combat_manager.reset_buttons = true;
-        if !enemies.is_empty() {
-            let mut obstructed_tiles: Vec<HexCoord> = tiles
-                .iter()
-                .filter_map(|t| if t.is_obstructed { Some(t.coord) } else { None })
-                .collect();
-            obstructed_tiles.push(player.hex_coord);
-            for (_, enemy) in &enemies {
-                obstructed_tiles.push(enemy.hex_coord);
+    combat_actions.send(combat_manager.state.ai_action());
+}
+
+/// Carries out the actions of the unit whose turn it is
+pub fn apply_combat_actions(
+    mut combat_actions: EventReader<CombatAction>,
+    mut move_finished: EventReader<MoveFinished>,
+    mut combat_manager: ResMut<CombatManager>,
+    mut combat_events: EventWriter<CombatEvent>,
+) {
+    for event in move_finished.iter() {
+        combat_manager.walk_finished(event.entity);
+    }
+    for action in combat_actions.iter() {
+        let events = combat_manager.apply(*action);
+        if events.is_empty() {
+            debug!("Ignoring {:?}, which isn't legal right now", action);
+        }
+        combat_events.send_batch(events);
+    }
+}
+
+/// Shows what happened in the fight, and copies the health, effects, energy and movement of every unit back to its
+/// entity
+#[allow(clippy::too_many_arguments)]
+pub fn present_combat_events(
+    mut combat_events: EventReader<CombatEvent>,
+    mut combat_manager: ResMut<CombatManager>,
+    mut players: Query<(&mut Player, &mut StatusEffects, Option<&mut RiggedEntity>), Without<Enemy>>,
+    mut enemies: Query<(&mut Enemy, &mut StatusEffects), Without<Player>>,
+    mut spells: Query<&mut Transform, With<Spell>>,
+    mut movers: Query<(&mut HexMover, &mut Transform), Without<Spell>>,
+    region_chunks: Res<RegionChunks>,
+    mut move_request_sender: EventWriter<MoveRequest>,
+    mut gi_lock_sender: EventWriter<GlobalInteractionLockEvent>,
+) {
+    let mut changed = false;
+    for event in combat_events.iter() {
+        changed = true;
+        match event {
+            CombatEvent::TurnStarted { unit, .. } => {
+                combat_manager.reset_buttons = true;
+                combat_manager.player_action = None;
+                let gi_state = match combat_manager.state.unit(*unit).controller {
+                    Controller::Player => GIState::Unlocked,
+                    Controller::Ai => GIState::Locked,
+                };
+                gi_lock_sender.send(GlobalInteractionLockEvent(gi_state));
+            }
+            CombatEvent::PhaseChanged { .. } => {
+                combat_manager.reset_buttons = true;
+                combat_manager.player_action = None;
+                // Tiles can't be clicked until an ability has been picked
+                gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Locked));
+            }
+            CombatEvent::Moved { unit, path, cost } => {
+                let target = *path.last().unwrap();
+                move_request_sender.send(MoveRequest::new(combat_manager.entity(*unit), target).with_max_cost(*cost));
             }
-            for (mut enemy_pos, mut enemy_data) in &mut enemies {
-                if enemy_data.ended_turn {
-                    continue;
+            CombatEvent::MoveUndone { unit, coord, .. } => {
+                // Put straight back, even if it's still walking
+                let entity = combat_manager.entity(*unit);
+                if let Ok((mut mover, mut transform)) = movers.get_mut(entity) {
+                    mover.halt();
+                    mover.coord = *coord;
+                    let position = coord.to_world();
+                    let elevation = *region_chunks.elevations().get(coord).unwrap_or(&0);
+                    transform.translation =
+                        Vec3::new(position.x, mover.height + elevation_height(elevation), position.y);
                 }
-                if enemy_data.path.is_none() {
-                    let mut obstructed_without_me = obstructed_tiles.clone();
-                    obstructed_without_me.retain(|&x| x != enemy_data.hex_coord);
-                    let e_path = astar(enemy_data.hex_coord, player.hex_coord, &obstructed_without_me);
-                    if let Some(e_some_path) = &mut e_path.clone() {
-                        e_some_path.remove(0);
-                        while e_some_path.len() > enemy_data.movement_range as usize {
-                            e_some_path.remove(e_some_path.len() - 1);
-                        }
-                        // println!("\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n{:#?} \n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n", e_some_path);
-                        // e_some_path = *e_some_path[1..enemy_data.movement_range as usize].to_vec();
-                        enemy_data.path = Some(e_some_path.clone());
-                    }
+                if let Ok((mut player, ..)) = players.get_mut(entity) {
+                    player.hex_coord = *coord;
+                } else if let Ok((mut enemy, _)) = enemies.get_mut(entity) {
+                    enemy.hex_coord = *coord;
                 }
-                if let Some(e_some_path) = &mut enemy_data.path.clone() {
-                    enemy_data.move_timer.tick(time.delta());
-                    if enemy_data.move_timer.just_finished() {
-                        if hex_distance(&enemy_data.hex_coord, &player.hex_coord) > enemy_data.attack_range {
-                            enemy_data.hex_coord = e_some_path[0];
-                            if !e_some_path.is_empty() {
-                                enemy_pos.translation.x = e_some_path[0].q as f32 * HORIZONTAL_SPACING
-                                    + e_some_path[0].r as f32 % 2.0 * HOR_OFFSET;
-                                enemy_pos.translation.z = e_some_path[0].r as f32 * VERTICAL_SPACING;
-                            };
-                            e_some_path.remove(0);
-                            enemy_data.path = Some(e_some_path.clone());
-                        }
-                        if hex_distance(&enemy_data.hex_coord, &player.hex_coord) <= enemy_data.attack_range {
-                            player.health.hp -= enemy_data.damage;
-                            enemy_data.ended_turn = true;
-                            enemy_data.path = None;
-                        }
-                    }
-                    if e_some_path.len() == 0 {
-                        enemy_data.ended_turn = true;
-                        enemy_data.path = None;
+            }
+            CombatEvent::AbilityUsed {
+                unit, ability, target, ..
+            } => {
+                let ability = &combat_manager.state.unit(*unit).abilities[*ability];
+                if ability.projectile {
+                    let position = target.to_world();
+                    for mut pos in &mut spells {
+                        pos.translation.x = position.x;
+                        pos.translation.z = position.y;
                     }
                 }
-            }
-            let mut ended_turn = true;
-            for (_, enemy_data) in &mut enemies {
-                if !enemy_data.ended_turn {
-                    ended_turn = false
+                if let (Some(animation), Ok((_, _, Some(mut rigged_entity)))) =
+                    (ability.animation, players.get_mut(combat_manager.entity(*unit)))
+                {
+                    rigged_entity.pend(animation);
                 }
             }
-            if ended_turn {
-                gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked));
-                combat_manager.turn = Turn::Player(Phase::Movement);
-                player.remaining_speed = player.stats.speed;
-            }
+            CombatEvent::CombatEnded { winner } => info!("The fight is over, won by {:?}", winner),
+            _ => {}
+        }
+    }
+    if !changed {
+        return;
+    }
+
+    for (unit, &entity) in combat_manager.state.units.iter().zip(&combat_manager.units) {
+        if let Ok((mut player, mut effects, _)) = players.get_mut(entity) {
+            player.health = unit.health.clone();
+            player.energy = unit.energy;
+            player.remaining_speed = unit.remaining_speed;
+            effects.0 = unit.effects.0.clone();
+        } else if let Ok((mut enemy, mut effects)) = enemies.get_mut(entity) {
+            enemy.health = unit.health.clone();
+            effects.0 = unit.effects.0.clone();
         }
     }
 }
 
 pub fn update_enemy_health(
     mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    enemies: Query<(Entity, &Enemy)>,
+    tile_assets: Res<TileAssets>,
+    mut enemies: Query<(Entity, &Enemy, &mut Handle<StandardMaterial>)>,
     mut gi_lock_sender: EventWriter<GlobalInteractionLockEvent>,
     mut map_context: ResMut<MapContext>,
 ) {
-    for (entity, enemy) in enemies.iter() {
+    for (entity, enemy, mut material) in &mut enemies {
         if enemy.health.hp <= 0.0 {
             commands.entity(entity).despawn();
             continue;
         }
         let health_percentage = enemy.health.hp / enemy.health.max_hp;
-        let new_material: Handle<StandardMaterial> =
-            materials.add(Color::rgba(1.0, 0.0, 0.0, health_percentage).into());
-
-        // Replace the old material with the new one
-        commands.entity(entity).insert(new_material);
+        material.set_if_neq(tile_assets.enemy_material(health_percentage));
     }
     if enemies.is_empty() {
         gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked));
@@ -530,10 +630,20 @@ pub fn update_enemy_health(
 #[derive(Component)]
 pub struct CombatObject;
 
-pub fn remove_combat_stuff(mut commands: Commands, ui_stuff: Query<Entity, With<CombatObject>>) {
+pub fn remove_combat_stuff(
+    mut commands: Commands,
+    ui_stuff: Query<Entity, With<CombatObject>>,
+    mut tiles: Query<&mut TileHighlight>,
+) {
     for ui_thing in &ui_stuff {
         commands.entity(ui_thing).despawn_recursive()
     }
+    for mut highlight in &mut tiles {
+        set_highlight(&mut highlight, HighlightLayer::AbilityRange, false);
+        set_highlight(&mut highlight, HighlightLayer::DangerZone, false);
+        set_highlight(&mut highlight, HighlightLayer::Splash, false);
+        set_highlight(&mut highlight, HighlightLayer::SplashHit, false);
+    }
 }
 
 #[derive(Component)]
@@ -541,33 +651,92 @@ pub enum InformationField {
     CurrentTurn,
     RemainingMovement,
     Energy,
+    /// The status effects of the unit whose turn it is
+    Effects,
+    /// The status effects of the enemy under the cursor
+    EnemyEffects,
+    /// What the selected ability can be expected to do to everyone it would hit on the tile under the cursor
+    DamagePreview,
 }
 
+/// Fills in the combat panel. Movement, energy and effects are those of the unit whose turn it is
 pub fn update_combat_information(
     mut fields: Query<(&mut Text, &InformationField)>,
     combat_manager: Res<CombatManager>,
-    player_query: Query<&Player>,
+    enemies: Query<(&Enemy, &StatusEffects)>,
+    tiles: Query<&Tile>,
 ) {
-    let player = player_query.single();
+    let state = &combat_manager.state;
+    let current = state.current().map(|id| state.unit(id));
+    let hovered = tiles.iter().find(|tile| tile.is_hovered).map(|tile| tile.coord);
+    let hovered_enemy = enemies.iter().find(|(enemy, _)| Some(enemy.hex_coord) == hovered);
     for (mut field, field_type) in &mut fields {
         match field_type {
             InformationField::CurrentTurn => {
-                field.sections[0].value = format!("Current Turn: {:?}", combat_manager.turn)
+                field.sections[0].value = format!("Current Turn: {:?}", combat_manager.state.turn)
             }
             InformationField::RemainingMovement => {
-                field.sections[0].value = format!("Remaining movement: {:?}", player.remaining_speed)
+                field.sections[0].value = current.map_or(String::new(), |unit| {
+                    format!("Remaining movement: {:?}", unit.remaining_speed)
+                })
+            }
+            InformationField::Energy => {
+                field.sections[0].value = current.map_or(String::new(), |unit| format!("Energy: {:?}", unit.energy))
+            }
+            InformationField::Effects => {
+                field.sections[0].value =
+                    current.map_or(String::new(), |unit| format!("Effects: {}", unit.effects.describe()))
+            }
+            InformationField::EnemyEffects => {
+                field.sections[0].value = match hovered_enemy {
+                    Some((_, effects)) => format!("Enemy effects: {}", effects.describe()),
+                    None => String::new(),
+                }
+            }
+            InformationField::DamagePreview => {
+                field.sections[0].value = describe_damage_preview(&combat_manager, hovered)
             }
-            InformationField::Energy => field.sections[0].value = format!("Energy: {:?}", player.energy),
         }
     }
 }
 
+/// Expected damage, and chances to hit and crit, for everyone the selected ability would hit if used on `target`
+fn describe_damage_preview(combat_manager: &CombatManager, target: Option<HexCoord>) -> String {
+    let (Some(AcitonType::Ability(ability)), Some(target), Some(id)) =
+        (combat_manager.player_action, target, combat_manager.state.current())
+    else {
+        return String::new();
+    };
+    let state = &combat_manager.state;
+    if !state.is_legal(&CombatAction::UseAbility { ability, target }) {
+        return String::new();
+    }
+    state
+        .preview_ability(id, ability, target)
+        .into_iter()
+        .map(|(unit, preview)| {
+            let name = if unit == id { "You" } else { "Enemy" };
+            format!(
+                "{}: {:.1} ({:.0}% hit, {:.0}% crit)",
+                name,
+                preview.expected,
+                preview.hit_chance * 100.0,
+                preview.crit_chance * 100.0
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 pub fn add_combat_stuff(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
+    player_query: Query<&Player>,
+    ability_book: Res<AbilityBook>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
+    let player = player_query.single();
     commands
         .spawn(PbrBundle {
             mesh: meshes.add(Mesh::from(shape::UVSphere {
@@ -598,120 +767,53 @@ pub fn add_combat_stuff(
         .insert(Name::new("combat ui"))
         .insert(CombatObject)
         .with_children(|parent| {
-            let fireball_icon = asset_server.load("2D/Fireball.png");
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        size: Size::height(Val::Percent(100.0)),
-                        // horizontally center child text
-                        justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        position_type: PositionType::Absolute,
-                        position: UiRect {
-                            right: Val::Px(0.0),
-                            top: Val::Px(0.0),
-                            ..default()
-                        },
-                        ..default()
-                    },
-                    background_color: NORMAL_BUTTON.into(),
-                    image: UiImage::new(fireball_icon),
-                    ..default()
-                })
-                .insert(ButtonType::CombatButton(CombatButtonType::Action(AcitonType::Fireball)))
-                .insert(ToggleButton::new())
-                .with_children(|parent| {
-                    parent
-                        .spawn(TextBundle::from_section(
-                            "Fireball",
-                            TextStyle {
-                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                        ))
-                        .insert(ButtonText {
-                            active_text: "Casting".to_string(),
-                            passive_text: "Fireball".to_string(),
-                        });
-                });
-            let smack_icon = asset_server.load("2D/Smack.png");
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        size: Size::height(Val::Percent(100.0)),
-                        // horizontally center child text
-                        justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        position_type: PositionType::Absolute,
-                        position: UiRect {
-                            right: Val::Px(200.0),
-                            top: Val::Px(0.0),
-                            ..default()
-                        },
-                        ..default()
-                    },
-                    background_color: NORMAL_BUTTON.into(),
-                    image: UiImage::new(smack_icon),
-                    ..default()
-                })
-                .insert(ButtonType::CombatButton(CombatButtonType::Action(AcitonType::Smack)))
-                .insert(ToggleButton::new())
-                .with_children(|parent| {
-                    parent
-                        .spawn(TextBundle::from_section(
-                            "Smack",
-                            TextStyle {
-                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
+            // The player's abilities, lined up from the right
+            for (index, ability) in ability_book.player_abilities(player).enumerate() {
+                let icon = ability
+                    .icon
+                    .as_ref()
+                    .map(|icon| UiImage::new(asset_server.load(icon.as_str())))
+                    .unwrap_or_default();
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            size: Size::height(Val::Percent(100.0)),
+                            // horizontally center child text
+                            justify_content: JustifyContent::Center,
+                            // vertically center child text
+                            align_items: AlignItems::Center,
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                right: Val::Px(200.0 * index as f32),
+                                top: Val::Px(0.0),
+                                ..default()
                             },
-                        ))
-                        .insert(ButtonText {
-                            active_text: "Smacking".to_string(),
-                            passive_text: "Smack".to_string(),
-                        });
-                });
-            let run_smack_icon = asset_server.load("2D/RunSmack.png");
-            parent
-                .spawn(ButtonBundle {
-                    style: Style {
-                        size: Size::height(Val::Percent(100.0)),
-                        // horizontally center child text
-                        justify_content: JustifyContent::Center,
-                        // vertically center child text
-                        align_items: AlignItems::Center,
-                        position_type: PositionType::Absolute,
-                        position: UiRect {
-                            right: Val::Px(400.0),
-                            top: Val::Px(0.0),
                             ..default()
                         },
+                        background_color: NORMAL_BUTTON.into(),
+                        image: icon,
                         ..default()
-                    },
-                    background_color: NORMAL_BUTTON.into(),
-                    image: UiImage::new(run_smack_icon),
-                    ..default()
-                })
-                .insert(ButtonType::CombatButton(CombatButtonType::Action(AcitonType::RunSmack)))
-                .insert(ToggleButton::new())
-                .with_children(|parent| {
-                    parent
-                        .spawn(TextBundle::from_section(
-                            "Run'n'Smack",
-                            TextStyle {
-                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                        ))
-                        .insert(ButtonText {
-                            active_text: "Running 'n' Smacking".to_string(),
-                            passive_text: "Run'n'Smack".to_string(),
-                        });
-                });
+                    })
+                    .insert(ButtonType::CombatButton(CombatButtonType::Action(AcitonType::Ability(
+                        index,
+                    ))))
+                    .insert(ToggleButton::new())
+                    .with_children(|parent| {
+                        parent
+                            .spawn(TextBundle::from_section(
+                                ability.name.clone(),
+                                TextStyle {
+                                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                    font_size: 40.0,
+                                    color: Color::rgb(0.9, 0.9, 0.9),
+                                },
+                            ))
+                            .insert(ButtonText {
+                                active_text: ability.active_text.clone(),
+                                passive_text: ability.name.clone(),
+                            });
+                    });
+            }
             // parent
             //     .spawn(ButtonBundle {
             //         style: Style {
@@ -784,6 +886,39 @@ pub fn add_combat_stuff(
                             passive_text: "End Phase".to_string(),
                         });
                 });
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::height(Val::Percent(100.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            left: Val::Px(200.0),
+                            top: Val::Px(0.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..default()
+                })
+                .insert(ButtonType::CombatButton(CombatButtonType::Action(AcitonType::UndoMove)))
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle::from_section(
+                            "Undo Move",
+                            TextStyle {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                font_size: 40.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                            },
+                        ))
+                        .insert(ButtonText {
+                            active_text: "Undid Move".to_string(),
+                            passive_text: "Undo Move".to_string(),
+                        });
+                });
         });
     commands
         .spawn(NodeBundle {
@@ -864,5 +999,88 @@ pub fn add_combat_stuff(
                     ..Default::default()
                 })
                 .insert(InformationField::Energy);
+            parent
+                .spawn(TextBundle {
+                    text: Text::from_section(
+                        "Effects: ",
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 40.0,
+                            color: Color::BLACK,
+                        },
+                    ),
+                    ..Default::default()
+                })
+                .insert(InformationField::Effects);
+            parent
+                .spawn(TextBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 40.0,
+                            color: Color::BLACK,
+                        },
+                    ),
+                    ..Default::default()
+                })
+                .insert(InformationField::EnemyEffects);
+            parent
+                .spawn(TextBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 40.0,
+                            color: Color::BLACK,
+                        },
+                    ),
+                    ..Default::default()
+                })
+                .insert(InformationField::DamagePreview);
         });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_wait_for_the_last_walk_to_finish() {
+        let elevations = (0..4)
+            .flat_map(|q| (0..4).map(move |r| (HexCoord::new(q, r), 0)))
+            .collect();
+        let units = vec![
+            CombatUnit::new(
+                Faction::Player,
+                Controller::Player,
+                HexCoord::new(0, 0),
+                Health::new(50.0),
+                3,
+            ),
+            CombatUnit::new(
+                Faction::Enemy,
+                Controller::Ai,
+                HexCoord::new(3, 3),
+                Health::new(10.0),
+                1,
+            ),
+        ];
+        let mut combat_manager = CombatManager::new();
+        combat_manager.state = CombatState::new(CombatGrid::new(elevations, []), units, 0);
+        combat_manager.units = vec![Entity::from_raw(0), Entity::from_raw(1)];
+        combat_manager.state.start();
+
+        assert!(!combat_manager.apply(CombatAction::Move(HexCoord::new(1, 0))).is_empty());
+        // Pressed again before the player has got there
+        assert!(combat_manager.apply(CombatAction::Move(HexCoord::new(2, 0))).is_empty());
+        assert_eq!(combat_manager.state.unit(UnitId(0)).coord, HexCoord::new(1, 0));
+        assert_eq!(combat_manager.state.unit(UnitId(0)).remaining_speed, 2);
+
+        combat_manager.walk_finished(Entity::from_raw(1));
+        assert!(combat_manager.apply(CombatAction::Move(HexCoord::new(2, 0))).is_empty());
+        combat_manager.walk_finished(Entity::from_raw(0));
+        assert!(!combat_manager.apply(CombatAction::Move(HexCoord::new(2, 0))).is_empty());
+        assert_eq!(combat_manager.state.unit(UnitId(0)).coord, HexCoord::new(2, 0));
+    }
+}
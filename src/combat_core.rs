@@ -0,0 +1,1197 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    astar, hex_distance, preview_hit, roll_hit, truncate_path_to_budget, Ability, DamagePreview, DamageStats, Element,
+    FriendlyFire, Health, HexCoord, ItemEffect, Stats, StatusEffect, StatusEffects,
+};
+
+/// Extra damage dealt when attacking a target standing lower down
+pub const HIGH_GROUND_DAMAGE_BONUS: f32 = 0.25;
+/// Extra range ranged attackers get when their target is standing lower down
+pub const HIGH_GROUND_RANGE_BONUS: i32 = 1;
+
+/// Damage multiplier for an attacker standing at elevation `attacker` hitting a target at elevation `target`
+pub fn high_ground_multiplier(attacker: i32, target: i32) -> f32 {
+    if attacker > target {
+        1.0 + HIGH_GROUND_DAMAGE_BONUS
+    } else {
+        1.0
+    }
+}
+
+/// Which side of a fight a unit is on
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Faction {
+    Player,
+    Enemy,
+}
+
+/// Who decides what a unit does on its turn
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Controller {
+    /// Through the combat UI, see `combat_system`
+    Player,
+    /// See [`CombatState::ai_action`]
+    Ai,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Turn {
+    Player(Phase),
+    /// An AI controlled unit is taking its turn
+    Ai,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Movement,
+    Action1,
+    Action2,
+}
+
+/// Index of a unit in [`CombatState::units`]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct UnitId(pub usize);
+
+/// A plain attack on a single unit, used by AI controlled units instead of abilities. Its damage is physical
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Attack {
+    pub range: i32,
+    pub damage: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CombatUnit {
+    pub faction: Faction,
+    pub controller: Controller,
+    /// Units with more initiative take their turns first
+    pub initiative: i32,
+    pub coord: HexCoord,
+    pub health: Health,
+    pub effects: StatusEffects,
+    /// Movement the unit gets every turn, before it's slowed
+    pub speed: i32,
+    /// Movement the unit has left this turn
+    pub remaining_speed: i32,
+    /// Scales the damage of the unit's abilities
+    pub stats: Stats,
+    /// Spent on using abilities. Movement left over at the end of the movement phase is turned into energy
+    pub energy: i32,
+    pub abilities: Vec<Ability>,
+    pub attack: Option<Attack>,
+    /// Decides whether the unit's hits land, and how much of the hits it takes get through
+    pub damage_stats: DamageStats,
+    /// Whether the unit has walked this turn. AI controlled units only walk once a turn
+    pub has_moved: bool,
+}
+
+impl CombatUnit {
+    pub fn new(faction: Faction, controller: Controller, coord: HexCoord, health: Health, speed: i32) -> CombatUnit {
+        CombatUnit {
+            faction,
+            controller,
+            initiative: speed,
+            coord,
+            health,
+            effects: StatusEffects::default(),
+            speed,
+            remaining_speed: speed,
+            stats: Stats::default(),
+            energy: 0,
+            abilities: vec![],
+            attack: None,
+            damage_stats: DamageStats::default(),
+            has_moved: false,
+        }
+    }
+
+    pub fn with_initiative(mut self, initiative: i32) -> CombatUnit {
+        self.initiative = initiative;
+        self
+    }
+
+    pub fn with_abilities(mut self, stats: Stats, energy: i32, abilities: Vec<Ability>) -> CombatUnit {
+        self.stats = stats;
+        self.energy = energy;
+        self.abilities = abilities;
+        self
+    }
+
+    pub fn with_attack(mut self, attack: Attack) -> CombatUnit {
+        self.attack = Some(attack);
+        self
+    }
+
+    pub fn with_damage_stats(mut self, damage_stats: DamageStats) -> CombatUnit {
+        self.damage_stats = damage_stats;
+        self
+    }
+
+    pub fn with_effects(mut self, effects: Vec<StatusEffect>) -> CombatUnit {
+        self.effects = StatusEffects(effects);
+        self
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.health.hp > 0.0
+    }
+}
+
+/// The terrain a fight takes place on
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct CombatGrid {
+    #[serde(with = "elevation_list")]
+    pub elevations: HashMap<HexCoord, i32>,
+    pub obstructed: HashSet<HexCoord>,
+}
+
+impl CombatGrid {
+    pub fn new(elevations: HashMap<HexCoord, i32>, obstructed: impl IntoIterator<Item = HexCoord>) -> CombatGrid {
+        CombatGrid {
+            elevations,
+            obstructed: obstructed.into_iter().collect(),
+        }
+    }
+
+    pub fn elevation(&self, coord: &HexCoord) -> i32 {
+        *self.elevations.get(coord).unwrap_or(&0)
+    }
+
+    /// Whether the tile is part of the grid and can be stood on
+    pub fn is_walkable(&self, coord: &HexCoord) -> bool {
+        self.elevations.contains_key(coord) && !self.obstructed.contains(coord)
+    }
+}
+
+/// JSON objects need strings as keys, so elevations are written out as a list of coordinates and elevations instead
+mod elevation_list {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(elevations: &HashMap<HexCoord, i32>, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut list: Vec<(&HexCoord, &i32)> = elevations.iter().collect();
+        list.sort_by_key(|(coord, _)| (coord.r, coord.q));
+        list.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<HexCoord, i32>, D::Error> {
+        Ok(Vec::<(HexCoord, i32)>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+/// The units of a fight, in the order they take their turns
+#[derive(PartialEq, Default, Debug)]
+pub struct TurnOrder {
+    pub queue: Vec<UnitId>,
+    /// Index of the unit whose turn it is
+    pub index: usize,
+    /// Goes up every time the queue comes back round to its start
+    pub round: u32,
+}
+
+impl TurnOrder {
+    /// The unit whose turn it is
+    pub fn current(&self) -> Option<UnitId> {
+        self.queue.get(self.index).copied()
+    }
+
+    /// Adds a unit joining the fight part way through, e.g. a summon. It takes its first turn at the end of the round
+    pub fn join(&mut self, unit: UnitId) {
+        if !self.queue.contains(&unit) {
+            self.queue.push(unit);
+        }
+    }
+
+    /// Hands the turn to the next unit, dropping the units that have left the fight on the way
+    pub fn advance(&mut self, in_fight: impl Fn(UnitId) -> bool) -> Option<UnitId> {
+        let current = self.current();
+        self.queue.retain(|&unit| Some(unit) == current || in_fight(unit));
+        let index = current
+            .and_then(|current| self.queue.iter().position(|&unit| unit == current))
+            .unwrap_or(0);
+        let mut next = index + 1;
+        if current.is_some_and(|current| !in_fight(current)) {
+            self.queue.remove(index);
+            next = index;
+        }
+        if next >= self.queue.len() {
+            next = 0;
+            self.round += 1;
+        }
+        self.index = next;
+        self.current()
+    }
+}
+
+/// Something the unit whose turn it is can do
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum CombatAction {
+    /// Walk towards a tile, as far as the unit's movement left allows
+    Move(HexCoord),
+    /// Use the ability at this index of the unit's abilities on a tile
+    UseAbility { ability: usize, target: HexCoord },
+    /// Use an item on the unit itself. The item comes out of the player's inventory, see `use_button_interaction`
+    UseItem(ItemEffect),
+    /// Hit a unit with the unit's [`Attack`], ending its turn
+    Attack(UnitId),
+    /// Take back the last walk of the movement phase, see [`CombatState::commit_moves`]
+    UndoMove,
+    /// Take back every walk of the movement phase
+    UndoAllMoves,
+    /// Make the walks taken so far this movement phase final. Sent by the game rather than picked by the player, see
+    /// [`CombatState::commit_moves`]
+    CommitMoves,
+    /// Move on to the next phase of the turn, or end it
+    EndPhase,
+}
+
+/// What happened as a result of a [`CombatAction`]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum CombatEvent {
+    TurnStarted {
+        unit: UnitId,
+        round: u32,
+    },
+    /// The unit couldn't act, e.g. because it's stunned, and its turn was skipped
+    TurnSkipped {
+        unit: UnitId,
+    },
+    TurnEnded {
+        unit: UnitId,
+    },
+    PhaseChanged {
+        unit: UnitId,
+        phase: Phase,
+    },
+    /// The unit walked along `path`, which doesn't include the tile it started on
+    Moved {
+        unit: UnitId,
+        path: Vec<HexCoord>,
+        cost: i32,
+    },
+    /// The unit was put back on `coord`, where it stood before the walks that were undone, getting `refund` movement
+    /// back
+    MoveUndone {
+        unit: UnitId,
+        coord: HexCoord,
+        refund: i32,
+    },
+    AbilityUsed {
+        unit: UnitId,
+        ability: usize,
+        target: HexCoord,
+        /// Energy spent on it
+        energy: i32,
+    },
+    ItemUsed {
+        unit: UnitId,
+        effect: ItemEffect,
+    },
+    Attacked {
+        unit: UnitId,
+        target: UnitId,
+    },
+    /// An attack or ability missed the unit
+    Missed {
+        unit: UnitId,
+    },
+    Healed {
+        unit: UnitId,
+        amount: f32,
+    },
+    /// Damage taken from an attack or ability, after armor, resistances and shields
+    Damaged {
+        unit: UnitId,
+        amount: f32,
+        critical: bool,
+    },
+    EffectApplied {
+        unit: UnitId,
+        effect: StatusEffect,
+    },
+    /// The unit's effects ticked at the end of its turn, changing its health by `hp_change`
+    EffectsTicked {
+        unit: UnitId,
+        hp_change: f32,
+    },
+    Died {
+        unit: UnitId,
+    },
+    /// Only units of `winner` are left standing
+    CombatEnded {
+        winner: Faction,
+    },
+}
+
+/// Something that happened in a fight: an action and what came of it
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct LogEntry {
+    /// The round the action was taken in
+    pub round: u32,
+    /// `None` for handing out the first turn
+    pub action: Option<CombatAction>,
+    pub events: Vec<CombatEvent>,
+}
+
+/// Everything that happened in a fight, along with how it started. Fights play out the same given the same start and
+/// actions, so a log can be replayed, e.g. from a bug report
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CombatLog {
+    /// Seeds every roll made during the fight
+    pub seed: u64,
+    pub grid: CombatGrid,
+    /// The units as they were before the first turn
+    pub units: Vec<CombatUnit>,
+    pub entries: Vec<LogEntry>,
+}
+
+impl CombatLog {
+    pub fn actions(&self) -> impl Iterator<Item = CombatAction> + '_ {
+        self.entries.iter().filter_map(|entry| entry.action)
+    }
+
+    /// Plays the fight out again from the start
+    pub fn replay(&self) -> CombatState {
+        let mut state = CombatState::new(self.grid.clone(), self.units.clone(), self.seed);
+        state.start();
+        for action in self.actions() {
+            state.apply(action);
+        }
+        state
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<CombatLog> {
+        serde_json::from_str(json)
+    }
+}
+
+/// A whole fight: who is in it, what it's fought on and whose turn it is.
+///
+/// The rules of combat live here, apart from Bevy, so they can be run and tested on their own. The systems of the
+/// [`CombatPlugin`](crate::CombatPlugin) turn clicks into [`CombatAction`]s, and the [`CombatEvent`]s that applying
+/// them produces into movement, animations and UI
+#[derive(Debug)]
+pub struct CombatState {
+    pub grid: CombatGrid,
+    pub units: Vec<CombatUnit>,
+    pub turn: Turn,
+    pub turn_order: TurnOrder,
+    /// Set once only one side is left standing
+    pub winner: Option<Faction>,
+    pub log: CombatLog,
+    /// Where the unit whose turn it is stood before each of its walks this movement phase, and what the walk cost
+    undoable_moves: Vec<(HexCoord, i32)>,
+    /// Makes every roll of the fight, seeded with the log's seed
+    rng: StdRng,
+}
+
+impl CombatState {
+    /// Queues the units up by initiative, with ties going to player controlled units. Nobody has the turn until
+    /// [`CombatState::start`]
+    pub fn new(grid: CombatGrid, units: Vec<CombatUnit>, seed: u64) -> CombatState {
+        let mut queue: Vec<UnitId> = (0..units.len()).map(UnitId).collect();
+        queue.sort_by_key(|&id| {
+            let unit = &units[id.0];
+            (-unit.initiative, unit.controller != Controller::Player, id)
+        });
+        let log = CombatLog {
+            seed,
+            grid: grid.clone(),
+            units: units.clone(),
+            entries: vec![],
+        };
+        CombatState {
+            grid,
+            units,
+            turn: Turn::Player(Phase::Movement),
+            turn_order: TurnOrder {
+                queue,
+                index: 0,
+                round: 1,
+            },
+            winner: None,
+            log,
+            undoable_moves: vec![],
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Hands the first turn out
+    pub fn start(&mut self) -> Vec<CombatEvent> {
+        let mut events = vec![];
+        self.next_turn(false, &mut events);
+        self.log.entries.push(LogEntry {
+            round: self.turn_order.round,
+            action: None,
+            events: events.clone(),
+        });
+        events
+    }
+
+    /// The unit whose turn it is
+    pub fn current(&self) -> Option<UnitId> {
+        if self.winner.is_some() {
+            return None;
+        }
+        self.turn_order.current()
+    }
+
+    pub fn unit(&self, id: UnitId) -> &CombatUnit {
+        &self.units[id.0]
+    }
+
+    /// Living units on the other side of the fight from `id`
+    pub fn hostiles(&self, id: UnitId) -> impl Iterator<Item = UnitId> + '_ {
+        let faction = self.unit(id).faction;
+        (0..self.units.len())
+            .map(UnitId)
+            .filter(move |&other| self.unit(other).faction != faction && self.unit(other).is_alive())
+    }
+
+    /// Tiles the unit can't walk through: obstructed ones, and those other units are standing on
+    fn obstacles(&self, id: UnitId) -> Vec<HexCoord> {
+        let occupied = self
+            .units
+            .iter()
+            .enumerate()
+            .filter(|&(other, unit)| other != id.0 && unit.is_alive())
+            .map(|(_, unit)| unit.coord);
+        self.grid.obstructed.iter().copied().chain(occupied).collect()
+    }
+
+    /// The path the unit would walk towards `target`, cut short where its movement runs out, and what it costs.
+    /// `None` if it can't take a single step that way
+    pub fn path(&self, id: UnitId, target: HexCoord) -> Option<(Vec<HexCoord>, i32)> {
+        let unit = self.unit(id);
+        let obstructed = self.obstacles(id);
+        if !self.grid.is_walkable(&target) || obstructed.contains(&target) {
+            return None;
+        }
+        let mut path = astar(unit.coord, target, &obstructed, &self.grid.elevations)?;
+        path.remove(0);
+        let cost = truncate_path_to_budget(&mut path, unit.coord, unit.remaining_speed, &self.grid.elevations);
+        if path.is_empty() {
+            return None;
+        }
+        Some((path, cost))
+    }
+
+    /// How far the unit's attack reaches from `from` against a target standing on `target`. Ranged attackers reach
+    /// further from high ground
+    fn attack_range_from(&self, attack: &Attack, from: &HexCoord, target: &HexCoord) -> i32 {
+        if attack.range > 1 && self.grid.elevation(from) > self.grid.elevation(target) {
+            attack.range + HIGH_GROUND_RANGE_BONUS
+        } else {
+            attack.range
+        }
+    }
+
+    pub fn is_legal(&self, action: &CombatAction) -> bool {
+        let Some(id) = self.current() else {
+            return false;
+        };
+        let unit = self.unit(id);
+        match *action {
+            CombatAction::Move(target) => {
+                let can_move = match self.turn {
+                    Turn::Player(phase) => phase == Phase::Movement,
+                    Turn::Ai => !unit.has_moved,
+                };
+                can_move && self.path(id, target).is_some()
+            }
+            CombatAction::UseAbility { ability, target } => {
+                let Some(ability) = unit.abilities.get(ability) else {
+                    return false;
+                };
+                matches!(self.turn, Turn::Player(Phase::Action1 | Phase::Action2))
+                    && unit.energy >= ability.energy_cost
+                    && self.grid.is_walkable(&target)
+                    && ability.can_target(&unit.coord, &target)
+            }
+            CombatAction::UseItem(_) => matches!(self.turn, Turn::Player(Phase::Action1 | Phase::Action2)),
+            CombatAction::Attack(target) => {
+                let Some(attack) = unit.attack else {
+                    return false;
+                };
+                let Some(other) = self.units.get(target.0) else {
+                    return false;
+                };
+                other.faction != unit.faction
+                    && other.is_alive()
+                    && hex_distance(&unit.coord, &other.coord)
+                        <= self.attack_range_from(&attack, &unit.coord, &other.coord)
+            }
+            CombatAction::UndoMove | CombatAction::UndoAllMoves | CombatAction::CommitMoves => {
+                self.turn == Turn::Player(Phase::Movement) && !self.undoable_moves.is_empty()
+            }
+            CombatAction::EndPhase => true,
+        }
+    }
+
+    /// Makes the walks taken so far this movement phase final, e.g. once they've revealed something. It's logged as
+    /// a [`CombatAction::CommitMoves`], so undos in a replay only reach as far back as they did the first time round
+    pub fn commit_moves(&mut self) {
+        self.apply(CombatAction::CommitMoves);
+    }
+
+    /// Everything the unit whose turn it is can do. Moves are listed once for every tile the unit can reach. Moving
+    /// towards a tile further away is legal too, and ends up on one of them
+    pub fn legal_actions(&self) -> Vec<CombatAction> {
+        let Some(id) = self.current() else {
+            return vec![];
+        };
+        let unit = self.unit(id);
+        let tiles = self.grid.elevations.keys().copied();
+        let moves = tiles
+            .clone()
+            .filter(|coord| hex_distance(&unit.coord, coord) <= unit.remaining_speed)
+            .filter(|&coord| {
+                self.path(id, coord)
+                    .is_some_and(|(path, _)| path.last() == Some(&coord))
+            })
+            .map(CombatAction::Move);
+        let abilities = (0..unit.abilities.len()).flat_map(|ability| {
+            tiles
+                .clone()
+                .map(move |target| CombatAction::UseAbility { ability, target })
+        });
+        let attacks = self.hostiles(id).map(CombatAction::Attack);
+        let undos = [CombatAction::UndoMove, CombatAction::UndoAllMoves];
+
+        let mut actions: Vec<CombatAction> = moves
+            .chain(abilities)
+            .chain(attacks)
+            .chain(undos)
+            .filter(|action| self.is_legal(action))
+            .collect();
+        actions.push(CombatAction::EndPhase);
+        actions
+    }
+
+    /// Carries out an action of the unit whose turn it is. Actions that aren't legal are ignored, and nothing happens
+    pub fn apply(&mut self, action: CombatAction) -> Vec<CombatEvent> {
+        let mut events = vec![];
+        let Some(id) = self.current() else {
+            return events;
+        };
+        if !self.is_legal(&action) {
+            return events;
+        }
+        let round = self.turn_order.round;
+
+        match action {
+            CombatAction::Move(target) => {
+                let (path, cost) = self.path(id, target).expect("Legal moves have a path");
+                let unit = &mut self.units[id.0];
+                if let Turn::Player(_) = self.turn {
+                    self.undoable_moves.push((unit.coord, cost));
+                }
+                unit.coord = *path.last().unwrap();
+                unit.remaining_speed -= cost;
+                unit.has_moved = true;
+                events.push(CombatEvent::Moved { unit: id, path, cost });
+            }
+            CombatAction::UndoMove | CombatAction::UndoAllMoves => {
+                let keep = match action {
+                    CombatAction::UndoMove => self.undoable_moves.len() - 1,
+                    _ => 0,
+                };
+                let undone = self.undoable_moves.split_off(keep);
+                let coord = undone[0].0;
+                let refund = undone.iter().map(|(_, cost)| cost).sum();
+                let unit = &mut self.units[id.0];
+                unit.coord = coord;
+                unit.remaining_speed += refund;
+                unit.has_moved = !self.undoable_moves.is_empty();
+                events.push(CombatEvent::MoveUndone {
+                    unit: id,
+                    coord,
+                    refund,
+                });
+            }
+            CombatAction::CommitMoves => self.undoable_moves.clear(),
+            CombatAction::UseAbility { ability, target } => {
+                self.use_ability(id, ability, target, &mut events);
+                self.end_action(id, &mut events);
+            }
+            CombatAction::UseItem(effect) => {
+                events.push(CombatEvent::ItemUsed { unit: id, effect });
+                let amount = effect.heal(&mut self.units[id.0].health);
+                if amount > 0.0 {
+                    events.push(CombatEvent::Healed { unit: id, amount });
+                }
+                self.end_action(id, &mut events);
+            }
+            CombatAction::Attack(target) => {
+                events.push(CombatEvent::Attacked { unit: id, target });
+                let damage = self.attack_damage(id, target);
+                self.hit(id, target, damage, Element::Physical, &mut events);
+                if self.winner.is_none() {
+                    self.end_turn(id, &mut events);
+                }
+            }
+            CombatAction::EndPhase => match self.turn {
+                Turn::Player(Phase::Movement) => {
+                    let unit = &mut self.units[id.0];
+                    unit.energy += unit.remaining_speed;
+                    unit.remaining_speed = 0;
+                    self.change_phase(id, Phase::Action1, &mut events);
+                }
+                Turn::Player(Phase::Action1) => self.change_phase(id, Phase::Action2, &mut events),
+                Turn::Player(Phase::Action2) | Turn::Ai => self.end_turn(id, &mut events),
+            },
+        }
+        self.log.entries.push(LogEntry {
+            round,
+            action: Some(action),
+            events: events.clone(),
+        });
+        events
+    }
+
+    /// What the AI controlled unit whose turn it is does next: attacking the closest unit on the other side if it's
+    /// in range, otherwise walking towards it and attacking once it gets there
+    pub fn ai_action(&self) -> CombatAction {
+        let Some(id) = self.current() else {
+            return CombatAction::EndPhase;
+        };
+        let unit = self.unit(id);
+        let Some(attack) = unit.attack else {
+            return CombatAction::EndPhase;
+        };
+        let Some(target) = self
+            .hostiles(id)
+            .min_by_key(|&other| (hex_distance(&unit.coord, &self.unit(other).coord), other))
+        else {
+            return CombatAction::EndPhase;
+        };
+        if self.is_legal(&CombatAction::Attack(target)) {
+            return CombatAction::Attack(target);
+        }
+        // Already walked as close as it could this turn
+        if unit.has_moved {
+            return CombatAction::EndPhase;
+        }
+
+        // Walk towards the target, stopping as soon as it's in range
+        let target_coord = self.unit(target).coord;
+        let obstructed = self.obstacles(id);
+        let destination = astar(unit.coord, target_coord, &obstructed, &self.grid.elevations).and_then(|mut path| {
+            path.remove(0);
+            truncate_path_to_budget(&mut path, unit.coord, unit.remaining_speed, &self.grid.elevations);
+            if let Some(in_range) = path.iter().position(|coord| {
+                hex_distance(coord, &target_coord) <= self.attack_range_from(&attack, coord, &target_coord)
+            }) {
+                path.truncate(in_range + 1);
+            }
+            path.last().copied()
+        });
+        match destination {
+            Some(destination) if destination != target_coord => CombatAction::Move(destination),
+            // Can't get any closer
+            _ => CombatAction::EndPhase,
+        }
+    }
+
+    /// Damage the unit's attack deals to `target` before any rolls, with the high ground bonus
+    fn attack_damage(&self, id: UnitId, target: UnitId) -> f32 {
+        let attack = self.unit(id).attack.expect("Only units with an attack attack");
+        let multiplier = high_ground_multiplier(
+            self.grid.elevation(&self.unit(id).coord),
+            self.grid.elevation(&self.unit(target).coord),
+        );
+        attack.damage * multiplier * self.unit(id).effects.damage_multiplier()
+    }
+
+    /// The units the ability would hit if used on `target`, and the damage it would deal each before any rolls
+    fn ability_hits(&self, id: UnitId, index: usize, target: HexCoord) -> Vec<(UnitId, f32)> {
+        let user = self.unit(id);
+        let ability = &user.abilities[index];
+        let affected_tiles = ability.affected_tiles(&user.coord, &target);
+        let mut hits = vec![];
+        for other in 0..self.units.len() {
+            let other = UnitId(other);
+            let unit = self.unit(other);
+            if !unit.is_alive() || !affected_tiles.contains(&unit.coord) {
+                continue;
+            }
+            let damage = ability.damage_at(&user.stats, &target, &unit.coord) * user.effects.damage_multiplier();
+            let damage = if unit.faction == user.faction {
+                // The user's own side is only hurt by friendly fire, and doesn't get the high ground bonus
+                if ability.friendly_fire == FriendlyFire::Off {
+                    continue;
+                }
+                damage * ability.friendly_fire.multiplier()
+            } else {
+                damage * high_ground_multiplier(self.grid.elevation(&user.coord), self.grid.elevation(&unit.coord))
+            };
+            hits.push((other, damage));
+        }
+        hits
+    }
+
+    /// What the unit using the ability on `target` can be expected to do to everyone it would hit
+    pub fn preview_ability(&self, id: UnitId, index: usize, target: HexCoord) -> Vec<(UnitId, DamagePreview)> {
+        let user = self.unit(id);
+        let Some(ability) = user.abilities.get(index) else {
+            return vec![];
+        };
+        self.ability_hits(id, index, target)
+            .into_iter()
+            .map(|(other, damage)| {
+                let preview = preview_hit(
+                    damage,
+                    ability.element,
+                    &user.damage_stats,
+                    &self.unit(other).damage_stats,
+                );
+                (other, preview)
+            })
+            .collect()
+    }
+
+    /// What the unit attacking `target` can be expected to do to it
+    pub fn preview_attack(&self, id: UnitId, target: UnitId) -> DamagePreview {
+        preview_hit(
+            self.attack_damage(id, target),
+            Element::Physical,
+            &self.unit(id).damage_stats,
+            &self.unit(target).damage_stats,
+        )
+    }
+
+    fn use_ability(&mut self, id: UnitId, index: usize, target: HexCoord, events: &mut Vec<CombatEvent>) {
+        let ability = self.unit(id).abilities[index].clone();
+        self.units[id.0].energy -= ability.energy_cost;
+        events.push(CombatEvent::AbilityUsed {
+            unit: id,
+            ability: index,
+            target,
+            energy: ability.energy_cost,
+        });
+
+        for (other, damage) in self.ability_hits(id, index, target) {
+            if !self.hit(id, other, damage, ability.element, events) {
+                continue;
+            }
+            for effect in &ability.effects {
+                self.units[other.0].effects.apply(*effect);
+                events.push(CombatEvent::EffectApplied {
+                    unit: other,
+                    effect: *effect,
+                });
+            }
+        }
+        for effect in &ability.self_effects {
+            self.units[id.0].effects.apply(*effect);
+            events.push(CombatEvent::EffectApplied {
+                unit: id,
+                effect: *effect,
+            });
+        }
+    }
+
+    /// Rolls for whether `attacker` lands a hit of `damage` on `id`, hurting it if it does and letting its shield soak
+    /// up what it can. Whether it landed
+    fn hit(
+        &mut self,
+        attacker: UnitId,
+        id: UnitId,
+        damage: f32,
+        element: Element,
+        events: &mut Vec<CombatEvent>,
+    ) -> bool {
+        let Some(hit) = roll_hit(
+            damage,
+            element,
+            &self.units[attacker.0].damage_stats,
+            &self.units[id.0].damage_stats,
+            &mut self.rng,
+        ) else {
+            events.push(CombatEvent::Missed { unit: id });
+            return false;
+        };
+        let unit = &mut self.units[id.0];
+        let was_alive = unit.is_alive();
+        let amount = unit.effects.absorb(hit.damage);
+        unit.health.hp -= amount;
+        events.push(CombatEvent::Damaged {
+            unit: id,
+            amount,
+            critical: hit.critical,
+        });
+        if was_alive && !self.unit(id).is_alive() {
+            self.die(id, events);
+        }
+        true
+    }
+
+    /// Ends the fight if the unit that died was the last of its side
+    fn die(&mut self, id: UnitId, events: &mut Vec<CombatEvent>) {
+        events.push(CombatEvent::Died { unit: id });
+        if self.winner.is_some() {
+            return;
+        }
+        let mut standing = self
+            .units
+            .iter()
+            .filter(|unit| unit.is_alive())
+            .map(|unit| unit.faction);
+        let first = standing.next();
+        if standing.all(|faction| Some(faction) == first) {
+            // Nobody standing at all counts as a loss for the player
+            let winner = first.unwrap_or(Faction::Enemy);
+            self.winner = Some(winner);
+            events.push(CombatEvent::CombatEnded { winner });
+        }
+    }
+
+    /// Moves on from the action phase the unit has just used up
+    fn end_action(&mut self, id: UnitId, events: &mut Vec<CombatEvent>) {
+        if self.winner.is_some() {
+            return;
+        }
+        // Units caught in their own blast can die on their turn
+        if self.turn == Turn::Player(Phase::Action1) && self.unit(id).is_alive() {
+            self.change_phase(id, Phase::Action2, events);
+        } else {
+            self.end_turn(id, events);
+        }
+    }
+
+    fn change_phase(&mut self, id: UnitId, phase: Phase, events: &mut Vec<CombatEvent>) {
+        self.undoable_moves.clear();
+        self.turn = Turn::Player(phase);
+        events.push(CombatEvent::PhaseChanged { unit: id, phase });
+    }
+
+    /// Ticks the unit's effects at the end of its turn
+    fn tick_effects(&mut self, id: UnitId, events: &mut Vec<CombatEvent>) {
+        let unit = &mut self.units[id.0];
+        let was_alive = unit.is_alive();
+        let hp = unit.health.hp;
+        unit.effects.end_turn(&mut unit.health);
+        let hp_change = unit.health.hp - hp;
+        events.push(CombatEvent::EffectsTicked { unit: id, hp_change });
+        if was_alive && !self.unit(id).is_alive() {
+            self.die(id, events);
+        }
+    }
+
+    fn end_turn(&mut self, id: UnitId, events: &mut Vec<CombatEvent>) {
+        events.push(CombatEvent::TurnEnded { unit: id });
+        self.tick_effects(id, events);
+        self.next_turn(true, events);
+    }
+
+    /// Hands the turn to the next unit able to act, skipping the turns of those that can't. Everyone being stunned at
+    /// once just lets the effects tick down
+    fn next_turn(&mut self, mut advance: bool, events: &mut Vec<CombatEvent>) {
+        while self.winner.is_none() {
+            let next = if advance {
+                let units = &self.units;
+                self.turn_order.advance(|id| units[id.0].is_alive())
+            } else {
+                self.turn_order.current()
+            };
+            advance = true;
+            let Some(id) = next else {
+                return;
+            };
+            if !self.unit(id).effects.can_act() {
+                events.push(CombatEvent::TurnSkipped { unit: id });
+                self.tick_effects(id, events);
+                continue;
+            }
+
+            self.undoable_moves.clear();
+            let unit = &mut self.units[id.0];
+            unit.remaining_speed = unit.effects.movement_range(unit.speed);
+            unit.has_moved = false;
+            self.turn = match unit.controller {
+                Controller::Player => Turn::Player(Phase::Movement),
+                Controller::Ai => Turn::Ai,
+            };
+            events.push(CombatEvent::TurnStarted {
+                unit: id,
+                round: self.turn_order.round,
+            });
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StatusKind;
+
+    /// A flat 7x7 grid
+    fn grid() -> CombatGrid {
+        let elevations = (0..7)
+            .flat_map(|q| (0..7).map(move |r| (HexCoord::new(q, r), 0)))
+            .collect();
+        CombatGrid::new(elevations, [])
+    }
+
+    fn smack() -> Ability {
+        serde_json::from_str(
+            r#"{ "name": "Smack", "active_text": "Smacking", "targeting": "Within", "range": 1,
+                "damage": { "flat": 10.0 } }"#,
+        )
+        .unwrap()
+    }
+
+    fn player(q: i32, r: i32, speed: i32) -> CombatUnit {
+        CombatUnit::new(
+            Faction::Player,
+            Controller::Player,
+            HexCoord::new(q, r),
+            Health::new(50.0),
+            speed,
+        )
+        .with_abilities(Stats::default(), 0, vec![smack()])
+    }
+
+    fn enemy(q: i32, r: i32, speed: i32, hp: f32) -> CombatUnit {
+        CombatUnit::new(
+            Faction::Enemy,
+            Controller::Ai,
+            HexCoord::new(q, r),
+            Health::new(hp),
+            speed,
+        )
+        .with_attack(Attack { range: 1, damage: 5.0 })
+    }
+
+    #[test]
+    fn turns_go_by_initiative() {
+        let mut state = CombatState::new(
+            grid(),
+            vec![player(0, 0, 2), enemy(6, 6, 3, 10.0), enemy(6, 0, 2, 10.0)],
+            0,
+        );
+        assert_eq!(state.turn_order.queue, vec![UnitId(1), UnitId(0), UnitId(2)]);
+
+        let events = state.start();
+        assert_eq!(
+            events,
+            vec![CombatEvent::TurnStarted {
+                unit: UnitId(1),
+                round: 1
+            }]
+        );
+        assert_eq!(state.turn, Turn::Ai);
+
+        let events = state.apply(CombatAction::EndPhase);
+        assert!(events.contains(&CombatEvent::TurnStarted {
+            unit: UnitId(0),
+            round: 1
+        }));
+        assert_eq!(state.turn, Turn::Player(Phase::Movement));
+    }
+
+    #[test]
+    fn moves_cost_movement() {
+        let mut state = CombatState::new(grid(), vec![player(0, 0, 2), enemy(6, 6, 1, 10.0)], 0);
+        state.start();
+        assert!(state.legal_actions().contains(&CombatAction::Move(HexCoord::new(2, 0))));
+        assert!(!state.legal_actions().contains(&CombatAction::Move(HexCoord::new(3, 0))));
+
+        state.apply(CombatAction::Move(HexCoord::new(2, 0)));
+        assert_eq!(state.unit(UnitId(0)).coord, HexCoord::new(2, 0));
+        assert_eq!(state.unit(UnitId(0)).remaining_speed, 0);
+        assert!(!state.is_legal(&CombatAction::Move(HexCoord::new(3, 0))));
+    }
+
+    #[test]
+    fn undoing_moves_gives_the_movement_back() {
+        let mut state = CombatState::new(grid(), vec![player(0, 0, 3), enemy(6, 6, 1, 10.0)], 0);
+        state.start();
+        assert!(!state.is_legal(&CombatAction::UndoMove));
+
+        state.apply(CombatAction::Move(HexCoord::new(1, 0)));
+        state.apply(CombatAction::Move(HexCoord::new(2, 0)));
+        let events = state.apply(CombatAction::UndoMove);
+        assert_eq!(
+            events,
+            vec![CombatEvent::MoveUndone {
+                unit: UnitId(0),
+                coord: HexCoord::new(1, 0),
+                refund: 1
+            }]
+        );
+        assert_eq!(state.unit(UnitId(0)).remaining_speed, 2);
+
+        state.apply(CombatAction::Move(HexCoord::new(3, 0)));
+        state.apply(CombatAction::UndoAllMoves);
+        assert_eq!(state.unit(UnitId(0)).coord, HexCoord::new(0, 0));
+        assert_eq!(state.unit(UnitId(0)).remaining_speed, 3);
+        assert!(!state.is_legal(&CombatAction::UndoMove));
+
+        // Movement left over still turns into energy
+        state.apply(CombatAction::Move(HexCoord::new(1, 0)));
+        state.commit_moves();
+        assert!(!state.is_legal(&CombatAction::UndoAllMoves));
+        state.apply(CombatAction::EndPhase);
+        assert_eq!(state.unit(UnitId(0)).energy, 2);
+    }
+
+    #[test]
+    fn ai_walks_up_and_attacks() {
+        let mut state = CombatState::new(grid(), vec![player(0, 0, 1), enemy(3, 0, 5, 10.0)], 0);
+        state.start();
+        assert_eq!(state.turn, Turn::Ai);
+
+        let action = state.ai_action();
+        assert_eq!(action, CombatAction::Move(HexCoord::new(1, 0)));
+        state.apply(action);
+        assert_eq!(state.ai_action(), CombatAction::Attack(UnitId(0)));
+        let events = state.apply(CombatAction::Attack(UnitId(0)));
+        assert!(events.contains(&CombatEvent::Damaged {
+            unit: UnitId(0),
+            amount: 5.0,
+            critical: false
+        }));
+        assert_eq!(state.unit(UnitId(0)).health.hp, 45.0);
+        assert_eq!(state.turn, Turn::Player(Phase::Movement));
+    }
+
+    #[test]
+    fn weakened_units_hit_softer() {
+        let weaken = StatusEffect {
+            kind: StatusKind::Weaken,
+            potency: 0.5,
+            turns: 1,
+        };
+        let mut state = CombatState::new(
+            grid(),
+            vec![player(0, 0, 1), enemy(1, 0, 5, 10.0).with_effects(vec![weaken])],
+            0,
+        );
+        state.start();
+        assert_eq!(state.preview_attack(UnitId(1), UnitId(0)).expected, 2.5);
+        state.apply(CombatAction::Attack(UnitId(0)));
+        assert_eq!(state.unit(UnitId(0)).health.hp, 47.5);
+    }
+
+    #[test]
+    fn stunned_units_skip_their_turn() {
+        let stun = StatusEffect {
+            kind: StatusKind::Stun,
+            potency: 0.0,
+            turns: 1,
+        };
+        let mut state = CombatState::new(
+            grid(),
+            vec![player(0, 0, 2), enemy(6, 6, 1, 10.0).with_effects(vec![stun])],
+            0,
+        );
+        state.start();
+        state.apply(CombatAction::EndPhase);
+        state.apply(CombatAction::EndPhase);
+        let events = state.apply(CombatAction::EndPhase);
+        assert!(events.contains(&CombatEvent::TurnSkipped { unit: UnitId(1) }));
+        assert!(events.contains(&CombatEvent::TurnStarted {
+            unit: UnitId(0),
+            round: 2
+        }));
+        assert!(state.unit(UnitId(1)).effects.can_act());
+    }
+
+    #[test]
+    fn killing_the_last_enemy_ends_the_fight() {
+        let mut state = CombatState::new(grid(), vec![player(0, 0, 2), enemy(1, 0, 1, 10.0)], 0);
+        state.start();
+        state.apply(CombatAction::EndPhase);
+        assert!(state.is_legal(&CombatAction::UseAbility {
+            ability: 0,
+            target: HexCoord::new(1, 0)
+        }));
+        let events = state.apply(CombatAction::UseAbility {
+            ability: 0,
+            target: HexCoord::new(1, 0),
+        });
+        assert!(events.contains(&CombatEvent::Died { unit: UnitId(1) }));
+        assert_eq!(
+            events.last(),
+            Some(&CombatEvent::CombatEnded {
+                winner: Faction::Player
+            })
+        );
+        assert!(state.legal_actions().is_empty());
+    }
+
+    #[test]
+    fn potions_heal_up_to_max_hp_and_use_up_an_action() {
+        let mut state = CombatState::new(grid(), vec![player(0, 0, 2), enemy(6, 6, 1, 10.0)], 0);
+        state.units[0].health.hp = 45.0;
+        state.start();
+        assert!(!state.is_legal(&CombatAction::UseItem(ItemEffect::Heal(0.25))));
+        state.apply(CombatAction::EndPhase);
+
+        let events = state.apply(CombatAction::UseItem(ItemEffect::Heal(0.25)));
+        assert!(events.contains(&CombatEvent::Healed {
+            unit: UnitId(0),
+            amount: 5.0
+        }));
+        assert_eq!(state.unit(UnitId(0)).health.hp, 50.0);
+        assert_eq!(state.turn, Turn::Player(Phase::Action2));
+
+        // Already at max HP, so there's nothing to heal, but the action is still used up
+        let events = state.apply(CombatAction::UseItem(ItemEffect::Heal(0.1)));
+        assert!(!events.iter().any(|event| matches!(event, CombatEvent::Healed { .. })));
+        assert_eq!(state.unit(UnitId(0)).health.hp, 50.0);
+        assert_eq!(state.turn, Turn::Ai);
+    }
+
+    #[test]
+    fn logs_replay_the_same_fight() {
+        let shaky = DamageStats {
+            accuracy: 0.5,
+            crit_chance: 0.5,
+            ..Default::default()
+        };
+        let units = vec![player(0, 0, 2).with_damage_stats(shaky), enemy(4, 0, 3, 30.0)];
+        let mut state = CombatState::new(grid(), units, 7);
+        state.start();
+        state.apply(CombatAction::Move(HexCoord::new(1, 0)));
+        state.apply(CombatAction::EndPhase);
+        state.apply(CombatAction::UseAbility {
+            ability: 0,
+            target: HexCoord::new(2, 0),
+        });
+        state.apply(CombatAction::EndPhase);
+        while state.turn == Turn::Ai {
+            state.apply(state.ai_action());
+        }
+
+        let log = CombatLog::from_json(&state.log.to_json()).unwrap();
+        let replayed = log.replay();
+        assert_eq!(replayed.log.entries, state.log.entries);
+        assert_eq!(replayed.unit(UnitId(0)).health.hp, state.unit(UnitId(0)).health.hp);
+    }
+
+    #[test]
+    fn replays_only_undo_moves_that_werent_committed() {
+        let mut state = CombatState::new(grid(), vec![player(0, 0, 3), enemy(6, 6, 1, 10.0)], 0);
+        state.start();
+        state.apply(CombatAction::Move(HexCoord::new(1, 0)));
+        state.commit_moves();
+        state.apply(CombatAction::Move(HexCoord::new(2, 0)));
+        state.apply(CombatAction::UndoAllMoves);
+        assert_eq!(state.unit(UnitId(0)).coord, HexCoord::new(1, 0));
+        assert_eq!(state.unit(UnitId(0)).remaining_speed, 2);
+
+        let replayed = state.log.replay();
+        assert_eq!(replayed.log.entries, state.log.entries);
+        assert_eq!(replayed.unit(UnitId(0)).coord, HexCoord::new(1, 0));
+        assert_eq!(replayed.unit(UnitId(0)).remaining_speed, 2);
+    }
+}
@@ -0,0 +1,288 @@
+use std::fs;
+
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+
+use crate::*;
+
+/// Plugin responsible for showing and exporting the [`CombatLog`] of the latest fight.
+///
+/// L opens a panel listing everything that happened in it, which scrolls with the mouse wheel. The log of the latest
+/// fight is kept once it's over, and its button in the panel writes it to combat_log.json. A log in that file can be
+/// played out again with [`CombatLog::replay`].
+pub struct CombatLogPlugin;
+
+impl Plugin for CombatLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LatestCombatLog>()
+            .add_system(
+                track_combat_log
+                    .after(present_combat_events)
+                    .run_if(resource_exists::<CombatManager>()),
+            )
+            .add_systems(
+                (
+                    toggle_combat_log_panel,
+                    update_combat_log_panel.after(toggle_combat_log_panel),
+                    scroll_combat_log,
+                    export_combat_log_button,
+                )
+                    .in_set(OnUpdate(GameState::InGame)),
+            );
+    }
+}
+
+/// The log of the fight going on, or of the last one once it's over
+#[derive(Resource, Default)]
+pub struct LatestCombatLog(pub Option<CombatLog>);
+
+/// Where [`export_combat_log_button`] writes the log to
+pub const COMBAT_LOG_PATH: &str = "combat_log.json";
+
+pub fn track_combat_log(
+    mut combat_events: EventReader<CombatEvent>,
+    combat_manager: Res<CombatManager>,
+    mut latest: ResMut<LatestCombatLog>,
+) {
+    if combat_events.iter().count() > 0 {
+        latest.0 = Some(combat_manager.state.log.clone());
+    }
+}
+
+/// How a unit is named in the log
+fn unit_name(log: &CombatLog, unit: UnitId) -> String {
+    match log.units.get(unit.0).map(|unit| (unit.faction, unit.controller)) {
+        Some((Faction::Player, Controller::Player)) => "Player".to_string(),
+        Some((Faction::Player, Controller::Ai)) => format!("Ally {}", unit.0),
+        _ => format!("Enemy {}", unit.0),
+    }
+}
+
+/// A line of the log panel describing the event, if it's worth one
+pub fn describe_combat_event(log: &CombatLog, event: &CombatEvent) -> Option<String> {
+    let name = |unit: &UnitId| unit_name(log, *unit);
+    let line = match event {
+        CombatEvent::TurnStarted { unit, round } => format!("Round {}: {}'s turn", round, name(unit)),
+        CombatEvent::TurnSkipped { unit } => format!("{} can't act and skips their turn", name(unit)),
+        CombatEvent::Moved { unit, path, cost } => {
+            format!("{} moves {} tiles for {} movement", name(unit), path.len(), cost)
+        }
+        CombatEvent::MoveUndone { unit, refund, .. } => {
+            format!("{} takes back their move, getting {} movement back", name(unit), refund)
+        }
+        CombatEvent::AbilityUsed {
+            unit,
+            ability,
+            target,
+            energy,
+        } => {
+            let ability = log.units[unit.0]
+                .abilities
+                .get(*ability)
+                .map_or("an ability", |ability| ability.name.as_str());
+            format!(
+                "{} uses {} on q={} r={} for {} energy",
+                name(unit),
+                ability,
+                target.q,
+                target.r,
+                energy
+            )
+        }
+        CombatEvent::ItemUsed { unit, effect } => format!("{} uses an item: {:?}", name(unit), effect),
+        CombatEvent::Healed { unit, amount } => format!("{} heals {:.1} HP", name(unit), amount),
+        CombatEvent::Attacked { unit, target } => format!("{} attacks {}", name(unit), name(target)),
+        CombatEvent::Missed { unit } => format!("{} is missed", name(unit)),
+        CombatEvent::Damaged {
+            unit,
+            amount,
+            critical: true,
+        } => format!("{} takes {:.1} damage from a critical hit", name(unit), amount),
+        CombatEvent::Damaged { unit, amount, .. } => format!("{} takes {:.1} damage", name(unit), amount),
+        CombatEvent::EffectApplied { unit, effect } => {
+            format!("{} gets {:?} for {} turns", name(unit), effect.kind, effect.turns)
+        }
+        CombatEvent::EffectsTicked { unit, hp_change } if *hp_change != 0.0 => {
+            format!("{}'s effects change their health by {:.1}", name(unit), hp_change)
+        }
+        CombatEvent::Died { unit } => format!("{} dies", name(unit)),
+        CombatEvent::CombatEnded { winner } => format!("The fight is over, won by the {:?} side", winner),
+        CombatEvent::TurnEnded { .. } | CombatEvent::PhaseChanged { .. } | CombatEvent::EffectsTicked { .. } => {
+            return None
+        }
+    };
+    Some(line)
+}
+
+#[derive(Component)]
+pub struct CombatLogPanel;
+
+/// The list of lines in the log panel, moved up and down to scroll through it
+#[derive(Component, Default)]
+pub struct CombatLogList {
+    position: f32,
+}
+
+#[derive(Component)]
+pub struct ExportCombatLogButton;
+
+pub fn toggle_combat_log_panel(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    panels: Query<Entity, With<CombatLogPanel>>,
+    asset_server: Res<AssetServer>,
+) {
+    if !keys.just_pressed(KeyCode::L) {
+        return;
+    }
+    if let Ok(panel) = panels.get_single() {
+        commands.entity(panel).despawn_recursive();
+        return;
+    }
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(460.0), Val::Percent(60.0)),
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(10.0),
+                    top: Val::Px(80.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+            ..default()
+        })
+        .insert(Name::new("combat log"))
+        .insert(CombatLogPanel)
+        .with_children(|parent| {
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(200.0), Val::Px(36.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::bottom(Val::Px(8.0)),
+                        ..default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..default()
+                })
+                .insert(ExportCombatLogButton)
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Export to JSON",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 24.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    ));
+                });
+            // Lines that don't fit are hidden, and scrolled to
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        flex_grow: 1.0,
+                        overflow: Overflow::Hidden,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                max_size: Size::UNDEFINED,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .insert(CombatLogList::default());
+                });
+        });
+}
+
+/// Fills the log panel with the latest log whenever it's opened or the log changes
+pub fn update_combat_log_panel(
+    mut commands: Commands,
+    latest: Res<LatestCombatLog>,
+    lists: Query<Entity, Added<CombatLogList>>,
+    all_lists: Query<Entity, With<CombatLogList>>,
+    asset_server: Res<AssetServer>,
+) {
+    let list = match (lists.get_single(), all_lists.get_single()) {
+        (Ok(list), _) => list,
+        (_, Ok(list)) if latest.is_changed() => list,
+        _ => return,
+    };
+    commands.entity(list).despawn_descendants();
+    let Some(ref log) = latest.0 else {
+        return;
+    };
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let lines = log
+        .entries
+        .iter()
+        .flat_map(|entry| entry.events.iter())
+        .filter_map(|event| describe_combat_event(log, event));
+    commands.entity(list).with_children(|parent| {
+        for line in lines {
+            parent.spawn(TextBundle::from_section(
+                line,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 18.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        }
+    });
+}
+
+/// Scrolls the log panel with the mouse wheel, keeping its lines from scrolling out of view
+pub fn scroll_combat_log(
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut lists: Query<(&mut CombatLogList, &mut Style, &Parent, &Node)>,
+    nodes: Query<&Node>,
+) {
+    for event in mouse_wheel.iter() {
+        for (mut list, mut style, parent, list_node) in &mut lists {
+            let container_height = nodes.get(parent.get()).map_or(0.0, |node| node.size().y);
+            let max_scroll = (list_node.size().y - container_height).max(0.0);
+            let dy = match event.unit {
+                MouseScrollUnit::Line => event.y * 20.0,
+                MouseScrollUnit::Pixel => event.y,
+            };
+            list.position = (list.position + dy).clamp(-max_scroll, 0.0);
+            style.position.top = Val::Px(list.position);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn export_combat_log_button(
+    mut buttons: Query<(&Interaction, &mut BackgroundColor), (Changed<Interaction>, With<ExportCombatLogButton>)>,
+    latest: Res<LatestCombatLog>,
+) {
+    for (interaction, mut colour) in &mut buttons {
+        match interaction {
+            Interaction::Clicked => {
+                *colour = PRESSED_BUTTON.into();
+                let Some(ref log) = latest.0 else {
+                    continue;
+                };
+                fs::write(COMBAT_LOG_PATH, log.to_json()).expect("Unable to write to file");
+                info!("Exported the combat log to {}", COMBAT_LOG_PATH);
+            }
+            Interaction::Hovered => *colour = HOVERED_BUTTON.into(),
+            Interaction::None => *colour = NORMAL_BUTTON.into(),
+        }
+    }
+}
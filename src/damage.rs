@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use bevy::reflect::{FromReflect, Reflect};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Armor it takes to halve physical damage. Every point of armor is worth a little less than the one before
+pub const ARMOR_SCALE: f32 = 10.0;
+
+/// The kind of damage a hit deals, which decides the resistance it's cut down by
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Element {
+    /// The only kind of damage armor protects against
+    #[default]
+    Physical,
+    Fire,
+    Poison,
+}
+
+/// How well a unit lands its hits, and how well it holds up to being hit
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct DamageStats {
+    /// Chance of landing a hit, before the target's evasion, between 0 and 1
+    pub accuracy: f32,
+    /// Taken off the accuracy of anyone trying to hit the unit
+    pub evasion: f32,
+    /// Chance of a hit that lands being critical, between 0 and 1
+    pub crit_chance: f32,
+    /// Multiplies the damage of critical hits
+    pub crit_multiplier: f32,
+    /// Cuts down physical damage, see [`ARMOR_SCALE`]
+    pub armor: f32,
+    /// Fraction of the damage of each element that is shrugged off. Negative resistances are weaknesses, adding to
+    /// the damage taken
+    pub resistances: HashMap<Element, f32>,
+}
+
+impl Default for DamageStats {
+    /// Always hits, never crits, and takes every hit in full
+    fn default() -> Self {
+        DamageStats {
+            accuracy: 1.0,
+            evasion: 0.0,
+            crit_chance: 0.0,
+            crit_multiplier: 1.5,
+            armor: 0.0,
+            resistances: HashMap::new(),
+        }
+    }
+}
+
+/// The damage stats a new character starts with
+pub fn player_damage_stats() -> DamageStats {
+    DamageStats {
+        accuracy: 0.9,
+        crit_chance: 0.1,
+        ..Default::default()
+    }
+}
+
+impl DamageStats {
+    pub fn resistance(&self, element: Element) -> f32 {
+        *self.resistances.get(&element).unwrap_or(&0.0)
+    }
+}
+
+/// A hit that landed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hit {
+    /// After armor and resistances
+    pub damage: f32,
+    pub critical: bool,
+}
+
+/// What a hit can be expected to do, shown while picking a target
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DamagePreview {
+    pub hit_chance: f32,
+    pub crit_chance: f32,
+    /// Damage dealt on average, counting misses and critical hits
+    pub expected: f32,
+}
+
+/// Chance of `attacker` landing a hit on `defender`
+pub fn hit_chance(attacker: &DamageStats, defender: &DamageStats) -> f32 {
+    (attacker.accuracy - defender.evasion).clamp(0.0, 1.0)
+}
+
+/// What's left of `damage` once the defender's resistance to `element`, and armor for physical damage, have cut it
+/// down
+pub fn mitigate(damage: f32, element: Element, defender: &DamageStats) -> f32 {
+    let damage = damage * (1.0 - defender.resistance(element));
+    let damage = match element {
+        Element::Physical => damage * ARMOR_SCALE / (ARMOR_SCALE + defender.armor.max(0.0)),
+        _ => damage,
+    };
+    damage.max(0.0)
+}
+
+/// Rolls for whether a hit of `damage` lands, and whether it's critical. `None` if it misses
+pub fn roll_hit(
+    damage: f32,
+    element: Element,
+    attacker: &DamageStats,
+    defender: &DamageStats,
+    rng: &mut impl Rng,
+) -> Option<Hit> {
+    if !rng.gen_bool(hit_chance(attacker, defender) as f64) {
+        return None;
+    }
+    let critical = rng.gen_bool(attacker.crit_chance.clamp(0.0, 1.0) as f64);
+    let multiplier = if critical { attacker.crit_multiplier } else { 1.0 };
+    Some(Hit {
+        damage: mitigate(damage * multiplier, element, defender),
+        critical,
+    })
+}
+
+/// What [`roll_hit`] comes to on average
+pub fn preview_hit(damage: f32, element: Element, attacker: &DamageStats, defender: &DamageStats) -> DamagePreview {
+    let hit_chance = hit_chance(attacker, defender);
+    let crit_chance = attacker.crit_chance.clamp(0.0, 1.0);
+    let multiplier = 1.0 + crit_chance * (attacker.crit_multiplier - 1.0);
+    DamagePreview {
+        hit_chance,
+        crit_chance,
+        expected: hit_chance * mitigate(damage * multiplier, element, defender),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armor_only_stops_physical_damage() {
+        let defender = DamageStats {
+            armor: ARMOR_SCALE,
+            resistances: HashMap::from([(Element::Fire, 0.25), (Element::Poison, -0.5)]),
+            ..Default::default()
+        };
+        assert_eq!(mitigate(10.0, Element::Physical, &defender), 5.0);
+        assert_eq!(mitigate(10.0, Element::Fire, &defender), 7.5);
+        assert_eq!(mitigate(10.0, Element::Poison, &defender), 15.0);
+    }
+
+    #[test]
+    fn previews_count_misses_and_crits() {
+        let attacker = DamageStats {
+            accuracy: 0.75,
+            crit_chance: 0.5,
+            crit_multiplier: 2.0,
+            ..Default::default()
+        };
+        let defender = DamageStats {
+            evasion: 0.25,
+            ..Default::default()
+        };
+        let preview = preview_hit(10.0, Element::Physical, &attacker, &defender);
+        assert_eq!(preview.hit_chance, 0.5);
+        assert_eq!(preview.expected, 7.5);
+    }
+}
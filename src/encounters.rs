@@ -0,0 +1,144 @@
+use rand::{distributions::WeightedIndex, prelude::*, rngs::StdRng};
+
+use crate::*;
+
+/// Plugin responsible for random encounters as the player walks through a region.
+///
+/// Regions with an [`EncounterTable`] roll its `chance` for every step the player takes outside of combat. When the
+/// roll succeeds, one of the table's groups is picked by weight, its enemies are generated and the fight happens on
+/// the spot (see [`MapContext::start_arena`]), putting the player back where they were once it has been won.
+pub struct EncounterPlugin;
+
+impl Plugin for EncounterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EncounterRng>()
+            .init_resource::<RegionEncounters>()
+            .add_system(
+                roll_encounters
+                    .in_set(OnUpdate(GameState::InGame))
+                    .after(follow_player_movement),
+            );
+    }
+}
+
+/// The random encounters a region can throw at the player, as stored in world.json
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub struct EncounterTable {
+    /// Chance of an encounter on every step, between 0 and 1
+    pub chance: f32,
+    pub groups: Vec<EncounterGroup>,
+}
+
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub struct EncounterGroup {
+    /// How likely the group is to be picked, relative to the other groups of the table
+    pub weight: u32,
+    pub enemies: Vec<EnemyTemplate>,
+}
+
+/// A kind of enemy in an [`EncounterGroup`], of which between `min_count` and `max_count` are generated
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub struct EnemyTemplate {
+    pub attack_range: i32,
+    pub movement_range: i32,
+    pub damage: f32,
+    pub hp: f32,
+    pub min_count: u32,
+    pub max_count: u32,
+    #[serde(default)]
+    pub damage_stats: DamageStats,
+}
+
+impl EncounterTable {
+    /// Rolls for an encounter, returning the enemies to fight if there is one
+    pub fn roll(&self, rng: &mut impl Rng) -> Option<Vec<Enemy>> {
+        if self.groups.is_empty() || !rng.gen_bool(self.chance.clamp(0.0, 1.0) as f64) {
+            return None;
+        }
+        let weights = WeightedIndex::new(self.groups.iter().map(|group| group.weight)).ok()?;
+        let group = &self.groups[weights.sample(rng)];
+        Some(group.generate(rng))
+    }
+}
+
+impl EncounterGroup {
+    /// The enemies are placed once the arena has been built, so they all start on (0, 0)
+    pub fn generate(&self, rng: &mut impl Rng) -> Vec<Enemy> {
+        self.enemies
+            .iter()
+            .flat_map(|template| {
+                let count = rng.gen_range(template.min_count..=template.max_count.max(template.min_count));
+                (0..count).map(|_| {
+                    Enemy::new(
+                        0,
+                        0,
+                        template.attack_range,
+                        template.movement_range,
+                        template.damage,
+                        template.hp,
+                    )
+                    .with_damage_stats(template.damage_stats.clone())
+                })
+            })
+            .collect()
+    }
+}
+
+/// The encounter table of the region the player is in, set by [`update_world`]
+#[derive(Resource, Default)]
+pub struct RegionEncounters(pub Option<EncounterTable>);
+
+/// Rolls random encounters. Seeded once per run, and the seed logged, so a run's encounters can be reproduced with
+/// [`EncounterRng::new`]
+#[derive(Resource)]
+pub struct EncounterRng(pub StdRng);
+
+impl EncounterRng {
+    pub fn new(seed: u64) -> EncounterRng {
+        info!("Encounter seed: {}", seed);
+        EncounterRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl Default for EncounterRng {
+    fn default() -> Self {
+        EncounterRng::new(rand::random())
+    }
+}
+
+/// Rolls the current region's [`EncounterTable`] for every step the player takes, starting a fight on the spot when
+/// an encounter comes up
+pub fn roll_encounters(
+    mut step_completed: EventReader<StepCompleted>,
+    mut player: Query<(Entity, &Player, &mut HexMover)>,
+    mut map_context: ResMut<MapContext>,
+    region_encounters: Res<RegionEncounters>,
+    mut rng: ResMut<EncounterRng>,
+    opt_combat_manager: Option<Res<CombatManager>>,
+    ui_state: Res<State<UIState>>,
+) {
+    let Ok((player_entity, player, mut mover)) = player.get_single_mut() else {
+        return;
+    };
+    let steps = step_completed.iter().filter(|e| e.entity == player_entity).count();
+    // No encounters in the middle of a fight, nor on a step that has already taken the player somewhere else
+    if steps == 0 || opt_combat_manager.is_some() || map_context.load_new_region || ui_state.0 != UIState::Null {
+        return;
+    }
+    let Some(ref table) = region_encounters.0 else {
+        return;
+    };
+
+    let rolled = (0..steps).find_map(|_| table.roll(&mut rng.0));
+    let Some(enemies) = rolled.filter(|enemies| !enemies.is_empty()) else {
+        return;
+    };
+    info!(
+        "Random encounter with {} enemies at coord q={} r={}",
+        enemies.len(),
+        player.hex_coord.q,
+        player.hex_coord.r
+    );
+    mover.halt();
+    map_context.start_arena(player.hex_coord, player.hex_coord, enemies);
+}
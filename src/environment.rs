@@ -0,0 +1,156 @@
+use crate::*;
+
+/// Plugin responsible for applying a region's [`EnvironmentSettings`] (clear colour, lighting and fog).
+///
+/// [`update_world`] inserts the [`RegionEnvironment`] of every region it loads, which is then applied here. The
+/// colours are set as soon as the game starts being set up, while the fog waits for the player camera to exist, and
+/// is added to it as soon as it's spawned.
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RegionEnvironment>()
+            .add_system(apply_region_environment.in_schedule(OnEnter(GameState::VisibleLoading)))
+            .add_system(
+                apply_region_environment
+                    .run_if(resource_changed::<RegionEnvironment>())
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(fog_new_player_camera);
+    }
+}
+
+/// How a region looks. Colours are stored as plain rgb(a) arrays to keep world.json readable
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub struct EnvironmentSettings {
+    pub clear_colour: [f32; 3],
+    pub ambient_colour: [f32; 3],
+    pub ambient_brightness: f32,
+    pub directional_light: Option<DirectionalLightSettings>,
+    pub fog: Option<FogSettingsData>,
+}
+
+impl Default for EnvironmentSettings {
+    /// The look of every region before environments were configurable
+    fn default() -> Self {
+        EnvironmentSettings {
+            // Color::ALICE_BLUE
+            clear_colour: [0.94, 0.97, 1.0],
+            ambient_colour: [1.0, 1.0, 1.0],
+            ambient_brightness: 0.6,
+            directional_light: None,
+            fog: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub struct DirectionalLightSettings {
+    pub colour: [f32; 3],
+    /// Illuminance in lux
+    pub illuminance: f32,
+    /// The direction the light travels in
+    pub direction: [f32; 3],
+    #[serde(default)]
+    pub shadows_enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub struct FogSettingsData {
+    pub colour: [f32; 4],
+    /// Distance from the camera at which the fog starts
+    pub start: f32,
+    /// Distance from the camera at which the fog is fully opaque
+    pub end: f32,
+}
+
+/// The environment of the currently loaded region
+#[derive(Resource, Default)]
+pub struct RegionEnvironment(pub EnvironmentSettings);
+
+/// Marks the directional light spawned for the current region, so it can be despawned on the next region change
+#[derive(Component)]
+struct RegionLight;
+
+fn apply_region_environment(
+    mut commands: Commands,
+    environment: Res<RegionEnvironment>,
+    region_lights: Query<Entity, With<RegionLight>>,
+    player_camera: Query<Entity, With<PlayerCameraMarker>>,
+) {
+    let settings = &environment.0;
+
+    commands.insert_resource(ClearColor(Color::rgb(
+        settings.clear_colour[0],
+        settings.clear_colour[1],
+        settings.clear_colour[2],
+    )));
+    commands.insert_resource(AmbientLight {
+        color: Color::rgb(
+            settings.ambient_colour[0],
+            settings.ambient_colour[1],
+            settings.ambient_colour[2],
+        ),
+        brightness: settings.ambient_brightness,
+    });
+
+    for light in &region_lights {
+        commands.entity(light).despawn_recursive();
+    }
+    if let Some(ref light) = settings.directional_light {
+        let direction = Vec3::from(light.direction);
+        // Straight up/down lights can't use Y as their up vector
+        let up = if direction.cross(Vec3::Y) == Vec3::ZERO {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+
+        commands
+            .spawn(DirectionalLightBundle {
+                directional_light: DirectionalLight {
+                    color: Color::rgb(light.colour[0], light.colour[1], light.colour[2]),
+                    illuminance: light.illuminance,
+                    shadows_enabled: light.shadows_enabled,
+                    ..default()
+                },
+                transform: Transform::default().looking_to(direction, up),
+                ..default()
+            })
+            .insert(Name::new("Region light"))
+            .insert(RegionLight);
+    }
+
+    if let Ok(camera) = player_camera.get_single() {
+        apply_region_fog(&mut commands, camera, settings);
+    }
+}
+
+/// Gives a player camera spawned after the region was loaded the region's fog
+fn fog_new_player_camera(
+    mut commands: Commands,
+    environment: Res<RegionEnvironment>,
+    player_camera: Query<Entity, Added<PlayerCameraMarker>>,
+) {
+    for camera in &player_camera {
+        apply_region_fog(&mut commands, camera, &environment.0);
+    }
+}
+
+fn apply_region_fog(commands: &mut Commands, camera: Entity, settings: &EnvironmentSettings) {
+    match settings.fog {
+        Some(ref fog) => {
+            commands.entity(camera).insert(FogSettings {
+                color: Color::rgba(fog.colour[0], fog.colour[1], fog.colour[2], fog.colour[3]),
+                falloff: FogFalloff::Linear {
+                    start: fog.start,
+                    end: fog.end,
+                },
+                ..default()
+            });
+        }
+        None => {
+            commands.entity(camera).remove::<FogSettings>();
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs;
+
+use crate::*;
+
+/// Plugin responsible for fast travel between waypoints.
+///
+/// Waypoint tiles are registered in the player's save the first time they're walked over. Afterwards the player can
+/// teleport to any of them from the world map, which can also be opened by clicking the waypoint tile they're
+/// standing on. Fast travel is unavailable while a [`CombatManager`] exists.
+pub struct FastTravelPlugin;
+
+impl Plugin for FastTravelPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Waypoint>()
+            .add_event::<FastTravelEvent>()
+            .add_system(fast_travel_button_interaction.in_set(OnUpdate(UIState::WorldMap)))
+            .add_system(on_fast_travel.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+#[derive(Reflect, FromReflect, Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Waypoint {
+    pub world: String,
+    pub coord: HexCoord,
+}
+
+pub struct FastTravelEvent {
+    pub waypoint: Waypoint,
+}
+
+#[derive(Component)]
+pub struct FastTravelButton(pub Waypoint);
+
+fn fast_travel_button_interaction(
+    mut interaction_query: Query<(&Interaction, &mut BackgroundColor, &FastTravelButton), Changed<Interaction>>,
+    mut fast_travel_sender: EventWriter<FastTravelEvent>,
+) {
+    for (interaction, mut background_colour, FastTravelButton(waypoint)) in &mut interaction_query {
+        match interaction {
+            Interaction::Clicked => fast_travel_sender.send(FastTravelEvent {
+                waypoint: waypoint.clone(),
+            }),
+            Interaction::Hovered => *background_colour = Color::rgb(0.34, 0.37, 0.60).into(),
+            Interaction::None => *background_colour = Color::rgb(0.22, 0.25, 0.48).into(),
+        }
+    }
+}
+
+pub fn on_fast_travel(
+    mut fast_travel_event: EventReader<FastTravelEvent>,
+    mut player: Query<(&mut Player, &mut HexMover)>,
+    mut map_context: ResMut<MapContext>,
+    mut next_ui_state: ResMut<NextState<UIState>>,
+    opt_combat_manager: Option<Res<CombatManager>>,
+) {
+    for event in fast_travel_event.iter() {
+        if opt_combat_manager.is_some() {
+            warn!("Can't fast travel during combat");
+            continue;
+        }
+
+        let Ok((mut p, mut mover)) = player.get_single_mut() else {
+            continue;
+        };
+
+        let waypoint = &event.waypoint;
+        info!(
+            "Fast travelling to region {} at coord q={} r={}",
+            waypoint.world, waypoint.coord.q, waypoint.coord.r
+        );
+
+        // Cancel any movement in progress, including the step under way, so it can't land the player back in the old
+        // region. The player is placed on the waypoint once the region has been reloaded
+        mover.halt();
+        p.hex_coord = waypoint.coord;
+
+        map_context.spawn_override = Some(waypoint.coord);
+        map_context.change_map(waypoint.world.clone());
+
+        let player_data = serde_json::to_string(&*p).unwrap();
+        fs::write("player_data.json", player_data)
+            .expect("It is very difficult for this error to occur. Stop messing with the code");
+
+        next_ui_state.set(UIState::Null);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+
+use crate::*;
+
+/// Plugin responsible for fog of war.
+///
+/// Tiles start hidden and are revealed once they come within [`SIGHT_RADIUS`] of the player. Revealed tiles are
+/// remembered per region in world.json; ones that are explored but currently out of view are drawn dimmed. Writing
+/// the whole world for every step would be slow in large regions, so they're saved in batches, see
+/// [`UnsavedExploration`].
+pub struct FogOfWarPlugin;
+
+impl Plugin for FogOfWarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<UnsavedExploration>()
+            .add_system(update_fog_of_war.in_set(OnUpdate(GameState::InGame)))
+            .add_system(save_exploration.before(update_world));
+    }
+}
+
+/// How often tiles explored since the last save are written to world.json, in seconds
+pub const EXPLORATION_SAVE_INTERVAL: f32 = 2.0;
+
+/// Tiles the player has explored that haven't been written to world.json yet
+#[derive(Resource)]
+pub struct UnsavedExploration {
+    /// The region the tiles are in
+    pub region: String,
+    pub coords: HashSet<HexCoord>,
+    timer: Timer,
+}
+
+impl Default for UnsavedExploration {
+    fn default() -> Self {
+        UnsavedExploration {
+            region: String::new(),
+            coords: HashSet::new(),
+            timer: Timer::from_seconds(EXPLORATION_SAVE_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+impl UnsavedExploration {
+    /// Writes the pending tiles to world.json straight away
+    pub fn flush(&mut self) {
+        if self.coords.is_empty() {
+            return;
+        }
+        MapContext::mark_explored(&self.region, &self.coords);
+        self.coords.clear();
+    }
+}
+
+/// Writes the explored tiles to world.json every [`EXPLORATION_SAVE_INTERVAL`], and before a new region is loaded so
+/// going back to the last one shows what was explored there
+pub fn save_exploration(mut unsaved: ResMut<UnsavedExploration>, map_context: Res<MapContext>, time: Res<Time>) {
+    let due = unsaved.timer.tick(time.delta()).just_finished();
+    if unsaved.coords.is_empty() || !(due || map_context.load_new_region) {
+        return;
+    }
+    unsaved.flush();
+}
+
+/// How many tiles away from the player can be seen, given an unobstructed line of sight
+pub const SIGHT_RADIUS: i32 = 3;
+
+/// Darkens a tile colour to show that the tile is explored but not currently in view
+pub fn fog_dim(colour: Color) -> Color {
+    Color::rgba(colour.r() * 0.4, colour.g() * 0.4, colour.b() * 0.4, colour.a())
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn update_fog_of_war(
+    mut tiles: Query<(&mut Tile, &mut Visibility)>,
+    mut enemies: Query<(&Enemy, &mut Visibility), Without<Tile>>,
+    mut chests: Query<(&Chest, &mut Visibility), (Without<Tile>, Without<Enemy>)>,
+    player: Query<&Player>,
+    map_context: Res<MapContext>,
+    mut unsaved: ResMut<UnsavedExploration>,
+    opt_combat_manager: Option<ResMut<CombatManager>>,
+    mut last_origin: Local<Option<HexCoord>>,
+    mut in_view: Local<Vec<HexCoord>>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let mut revealed = false;
+
+    // Only recalculate when the player has moved or a new region has just been spawned in
+    let region_spawned = tiles.iter_mut().any(|(tile, _)| tile.is_added());
+    if region_spawned || *last_origin != Some(player.hex_coord) {
+        *last_origin = Some(player.hex_coord);
+
+        let obstructed_tiles: Vec<HexCoord> = tiles
+            .iter()
+            .filter_map(|(t, _)| if t.is_obstructed { Some(t.coord) } else { None })
+            .collect();
+
+        *in_view = field_of_view(player.hex_coord, SIGHT_RADIUS, &obstructed_tiles);
+
+        let mut newly_explored = vec![];
+        for (mut tile, mut visibility) in &mut tiles {
+            tile.is_visible = in_view.contains(&tile.coord);
+
+            if tile.is_visible && !tile.is_explored {
+                tile.is_explored = true;
+                newly_explored.push(tile.coord);
+            }
+
+            *visibility = if tile.is_explored {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+
+        if !newly_explored.is_empty() {
+            if unsaved.region != map_context.id {
+                let previous = std::mem::replace(&mut unsaved.region, map_context.id.clone());
+                if !unsaved.coords.is_empty() {
+                    MapContext::mark_explored(&previous, &unsaved.coords);
+                    unsaved.coords.clear();
+                }
+            }
+            unsaved.coords.extend(newly_explored);
+            revealed = true;
+        }
+
+        for (chest, mut visibility) in &mut chests {
+            let explored = tiles
+                .iter()
+                .any(|(tile, _)| tile.coord == chest.hex_coord && tile.is_explored);
+
+            *visibility = if explored {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+
+    // Enemies move on their own, so they are checked every frame against the last calculated view
+    for (enemy, mut visibility) in &mut enemies {
+        let in_sight = in_view.contains(&enemy.hex_coord);
+        revealed |= in_sight && *visibility == Visibility::Hidden;
+        *visibility = if in_sight {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+
+    // Walking back can't hide what the player has already seen
+    if let (true, Some(mut combat_manager)) = (revealed, opt_combat_manager) {
+        combat_manager.state.commit_moves();
+    }
+}
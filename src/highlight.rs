@@ -0,0 +1,109 @@
+use crate::*;
+
+/// Plugin responsible for turning each tile's [`TileHighlight`] into the material it's drawn with.
+///
+/// Gameplay systems never pick a tile's colour themselves. They add or remove highlight layers, and the highest
+/// priority layer a tile has decides how it looks.
+pub struct TileHighlightPlugin;
+
+impl Plugin for TileHighlightPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(apply_tile_highlights.in_base_set(CoreSet::PostUpdate));
+    }
+}
+
+/// A reason for a tile to be highlighted, from lowest to highest priority
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum HighlightLayer {
+    /// Obstructed, waypoint or plain ground
+    BaseTerrain,
+    /// Entrance to another region
+    Subregion,
+    Hover,
+    /// Part of the path the player is about to walk
+    PathPreview,
+    /// Can be targeted by the selected combat action
+    AbilityRange,
+    /// Would be hit by the selected combat action, used on the hovered tile
+    Splash,
+    /// Occupied by an enemy during combat
+    DangerZone,
+    /// Occupied by someone who would be hit by the selected combat action, used on the hovered tile
+    SplashHit,
+    /// Where the player is heading
+    Selection,
+}
+
+#[derive(Component, Default, Debug)]
+pub struct TileHighlight {
+    layers: Vec<HighlightLayer>,
+}
+
+impl TileHighlight {
+    /// The highlight of a tile that nothing is happening to
+    pub fn new(tile: &Tile) -> TileHighlight {
+        let mut highlight = TileHighlight::default();
+        highlight.add(HighlightLayer::BaseTerrain);
+        if tile.sub_region_id.is_some() {
+            highlight.add(HighlightLayer::Subregion);
+        }
+        highlight
+    }
+
+    pub fn has(&self, layer: HighlightLayer) -> bool {
+        self.layers.contains(&layer)
+    }
+
+    pub fn add(&mut self, layer: HighlightLayer) {
+        if !self.has(layer) {
+            self.layers.push(layer);
+        }
+    }
+
+    pub fn remove(&mut self, layer: HighlightLayer) {
+        self.layers.retain(|l| *l != layer);
+    }
+
+    /// The layer that decides how the tile looks
+    pub fn top(&self) -> Option<HighlightLayer> {
+        self.layers.iter().max().copied()
+    }
+}
+
+/// Adds or removes a layer, leaving the component untouched if it's already in the right state. Saves every tile's
+/// material being recomposed when a system checks all of them each frame
+pub fn set_highlight(highlight: &mut Mut<TileHighlight>, layer: HighlightLayer, enabled: bool) {
+    if highlight.has(layer) != enabled {
+        if enabled {
+            highlight.add(layer);
+        } else {
+            highlight.remove(layer);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn apply_tile_highlights(
+    mut tiles: Query<
+        (&Tile, &TileHighlight, &mut Handle<StandardMaterial>),
+        Or<(Changed<Tile>, Changed<TileHighlight>)>,
+    >,
+    tile_assets: Res<TileAssets>,
+) {
+    for (tile, highlight, mut material) in &mut tiles {
+        let new_material = match highlight.top() {
+            Some(HighlightLayer::Selection) => tile_assets.tile_material(TileVisual::Selection, false),
+            Some(HighlightLayer::SplashHit) => tile_assets.tile_material(TileVisual::SplashHit, false),
+            Some(HighlightLayer::DangerZone) => tile_assets.tile_material(TileVisual::Danger, false),
+            Some(HighlightLayer::Splash) => tile_assets.tile_material(TileVisual::Splash, false),
+            Some(HighlightLayer::AbilityRange) => tile_assets.tile_material(TileVisual::Target, false),
+            Some(HighlightLayer::PathPreview) => tile_assets.tile_material(TileVisual::Path, false),
+            Some(HighlightLayer::Hover) => tile_assets.tile_material(TileVisual::Hovered, false),
+            // Ground the player can't currently see is dimmed by the fog of war
+            Some(HighlightLayer::Subregion | HighlightLayer::BaseTerrain) | None => {
+                tile_assets.tile_material(TileVisual::resting(tile), !tile.is_visible)
+            }
+        };
+        material.set_if_neq(new_material);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::*;
+
+/// Plugin responsible for showing the order units take their turns in during combat.
+///
+/// Every unit in a fight has a [`Combatant`], from which [`start_combat`] sets up its [`CombatUnit`]. The units are
+/// queued up by initiative in the [`TurnOrder`] of the [`CombatState`] and take their turns one at a time, which is
+/// shown in a bar at the top of the screen.
+pub struct InitiativePlugin;
+
+impl Plugin for InitiativePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(add_turn_order_bar.run_if(resource_added::<CombatManager>()))
+            .add_system(
+                update_turn_order_bar
+                    .after(present_combat_events)
+                    .run_if(resource_exists::<CombatManager>()),
+            );
+    }
+}
+
+/// A unit taking part in fights
+#[derive(Component, Clone, Debug)]
+pub struct Combatant {
+    pub faction: Faction,
+    pub controller: Controller,
+    /// Units with more initiative take their turns first. An enemy's is its movement range. The player's speed stat
+    /// is used instead of theirs, so it keeps up with them getting faster
+    pub initiative: i32,
+}
+
+impl Combatant {
+    pub fn new(faction: Faction, controller: Controller, initiative: i32) -> Combatant {
+        Combatant {
+            faction,
+            controller,
+            initiative,
+        }
+    }
+}
+
+pub fn add_turn_order_bar(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Px(70.0)),
+                position_type: PositionType::Absolute,
+                position: UiRect::top(Val::Px(0.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Name::new("turn order bar"))
+        .insert((TurnOrderBar, CombatObject));
+}
+
+#[derive(Component)]
+pub struct TurnOrderBar;
+
+/// Redraws the turn order bar whenever the order changes, starting from the unit whose turn it is
+#[allow(clippy::type_complexity)]
+pub fn update_turn_order_bar(
+    mut commands: Commands,
+    combat_manager: Res<CombatManager>,
+    bars: Query<Entity, With<TurnOrderBar>>,
+    asset_server: Res<AssetServer>,
+    // The bar, queue, index and number of units standing last drawn
+    mut drawn: Local<Option<(Entity, Vec<UnitId>, usize, usize)>>,
+) {
+    let state = &combat_manager.state;
+    let order = &state.turn_order;
+    let Ok(bar) = bars.get_single() else {
+        return;
+    };
+    let standing = state.units.iter().filter(|unit| unit.is_alive()).count();
+    let shown = (bar, order.queue.clone(), order.index, standing);
+    if drawn.as_ref() == Some(&shown) {
+        return;
+    }
+    *drawn = Some(shown);
+
+    commands.entity(bar).despawn_descendants();
+    let len = order.queue.len();
+    let upcoming = (0..len).map(|offset| order.queue[(order.index + offset) % len]);
+    commands.entity(bar).with_children(|parent| {
+        for (position, unit) in upcoming.enumerate() {
+            let unit = state.unit(unit);
+            if !unit.is_alive() {
+                continue;
+            }
+            let (label, colour) = match (unit.faction, unit.controller) {
+                (Faction::Player, Controller::Player) => ("You", Color::rgb(0.2, 0.6, 0.2)),
+                (Faction::Player, Controller::Ai) => ("Ally", Color::rgb(0.2, 0.4, 0.8)),
+                (Faction::Enemy, _) => ("Enemy", Color::rgb(0.7, 0.15, 0.15)),
+            };
+            // The unit whose turn it is stands out from the rest
+            let size = if position == 0 { 120.0 } else { 90.0 };
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(size), Val::Px(size / 2.0)),
+                        margin: UiRect::all(Val::Px(4.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: colour.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        label,
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: size / 4.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    ));
+                });
+        }
+    });
+}
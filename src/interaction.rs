@@ -0,0 +1,182 @@
+use crate::*;
+
+/// Plugin responsible for the player interacting with things in the world, such as chests and signs.
+///
+/// Anything with an [`Interactable`] can be interacted with by clicking its tile, or pressing F, once the player is
+/// within its range. Clicking one that's further away walks the player up to it first. Each kind of interactable has
+/// its own event, e.g. [`ChestOpenEvent`], which whatever handles that kind of object listens for. New kinds of
+/// objects, like NPCs or doors, only need an [`InteractionKind`] and an event sent by [`dispatch_interactions`].
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<InteractEvent>()
+            .add_system(
+                request_interactions
+                    .in_set(OnUpdate(GameState::InGame))
+                    .after(follow_player_movement),
+            )
+            .add_system(dispatch_interactions.after(request_interactions));
+    }
+}
+
+/// How far away the player can be from most interactables, in tiles
+pub const DEFAULT_INTERACTION_RANGE: i32 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InteractionKind {
+    /// Needs a [`Chest`] on the same entity
+    Chest,
+    /// Needs a [`Sign`] on the same entity
+    Sign,
+}
+
+#[derive(Component, Debug)]
+pub struct Interactable {
+    pub kind: InteractionKind,
+    pub hex_coord: HexCoord,
+    /// The furthest the player can be from the interactable's tile, in tiles
+    pub range: i32,
+}
+
+impl Interactable {
+    pub fn new(kind: InteractionKind, hex_coord: HexCoord) -> Interactable {
+        Interactable {
+            kind,
+            hex_coord,
+            range: DEFAULT_INTERACTION_RANGE,
+        }
+    }
+
+    pub fn with_range(mut self, range: i32) -> Interactable {
+        self.range = range;
+        self
+    }
+
+    pub fn in_range_of(&self, coord: &HexCoord) -> bool {
+        hex_distance(&self.hex_coord, coord) <= self.range
+    }
+}
+
+/// The player interacts with an [`Interactable`] entity, which must be in range
+pub struct InteractEvent {
+    pub entity: Entity,
+}
+
+/// The tile within range of `interactable` closest to `from`, which the player walks to before interacting with it
+fn approach_tile(interactable: &Interactable, from: &HexCoord, region_chunks: &RegionChunks) -> Option<HexCoord> {
+    let centre = interactable.hex_coord;
+    let range = interactable.range;
+    let obstructed_tiles = region_chunks.obstructed_tiles();
+
+    (centre.r - range..=centre.r + range)
+        .flat_map(|r| (centre.q - range..=centre.q + range).map(move |q| HexCoord::new(q, r)))
+        .filter(|coord| {
+            *coord != centre
+                && interactable.in_range_of(coord)
+                && region_chunks.elevations().contains_key(coord)
+                && !obstructed_tiles.contains(coord)
+        })
+        .min_by_key(|coord| hex_distance(coord, from))
+}
+
+/// Turns clicks on interactables, and pressing F, into [`InteractEvent`]s, walking the player up to the
+/// interactable first if needs be
+#[allow(clippy::too_many_arguments)]
+pub fn request_interactions(
+    mut tile_clicks: EventReader<TileClicked>,
+    mut move_finished: EventReader<MoveFinished>,
+    keys: Res<Input<KeyCode>>,
+    interactables: Query<(Entity, &Interactable)>,
+    player: Query<(Entity, &Player)>,
+    region_chunks: Res<RegionChunks>,
+    gi_state: Res<State<GIState>>,
+    ui_state: Res<State<UIState>>,
+    opt_combat_manager: Option<Res<CombatManager>>,
+    mut move_request_sender: EventWriter<MoveRequest>,
+    mut interact_sender: EventWriter<InteractEvent>,
+    // The interactable the player is walking up to
+    mut approaching: Local<Option<Entity>>,
+) {
+    let Ok((player_entity, player)) = player.get_single() else {
+        return;
+    };
+
+    for event in move_finished.iter().filter(|e| e.entity == player_entity) {
+        let Some(entity) = approaching.take() else {
+            continue;
+        };
+        // Might have been stopped short of it, e.g. by walking into a subregion
+        if let Ok((_, interactable)) = interactables.get(entity) {
+            if interactable.in_range_of(&event.coord) {
+                interact_sender.send(InteractEvent { entity });
+            }
+        }
+    }
+
+    // Clicks are read even when they can't be acted upon, so they don't pile up
+    let clicks: Vec<HexCoord> = tile_clicks.iter().map(|click| click.coord).collect();
+    if opt_combat_manager.is_some() {
+        return;
+    }
+
+    for coord in clicks {
+        let Some((entity, interactable)) = interactables.iter().find(|(_, i)| i.hex_coord == coord) else {
+            continue;
+        };
+        if interactable.in_range_of(&player.hex_coord) {
+            interact_sender.send(InteractEvent { entity });
+        } else if let Some(target) = approach_tile(interactable, &player.hex_coord, &region_chunks) {
+            *approaching = Some(entity);
+            move_request_sender.send(MoveRequest::new(player_entity, target));
+        }
+    }
+
+    if keys.just_pressed(KeyCode::F) && gi_state.0 == GIState::Unlocked && ui_state.0 == UIState::Null {
+        let nearest = interactables
+            .iter()
+            .filter(|(_, interactable)| interactable.in_range_of(&player.hex_coord))
+            .min_by_key(|(_, interactable)| hex_distance(&interactable.hex_coord, &player.hex_coord));
+        if let Some((entity, _)) = nearest {
+            interact_sender.send(InteractEvent { entity });
+        }
+    }
+}
+
+/// Sends the event for the kind of thing being interacted with
+pub fn dispatch_interactions(
+    mut interact_events: EventReader<InteractEvent>,
+    interactables: Query<(&Interactable, Option<&Chest>, Option<&Sign>)>,
+    mut chest_open_sender: EventWriter<ChestOpenEvent>,
+    mut sign_read_sender: EventWriter<SignReadEvent>,
+) {
+    for event in interact_events.iter() {
+        let Ok((interactable, chest, sign)) = interactables.get(event.entity) else {
+            continue;
+        };
+        let coord = interactable.hex_coord;
+        match interactable.kind {
+            InteractionKind::Chest => {
+                let Some(chest) = chest else {
+                    warn!("The chest at coord q={} r={} has no Chest to open", coord.q, coord.r);
+                    continue;
+                };
+                info!("Opening a chest at coord q={} r={}", coord.q, coord.r);
+                chest_open_sender.send(ChestOpenEvent {
+                    chest_ent: event.entity,
+                    chest: chest.clone(),
+                });
+            }
+            InteractionKind::Sign => {
+                let Some(sign) = sign else {
+                    warn!("The sign at coord q={} r={} has no Sign to read", coord.q, coord.r);
+                    continue;
+                };
+                sign_read_sender.send(SignReadEvent {
+                    sign_ent: event.entity,
+                    text: sign.text.clone(),
+                });
+            }
+        }
+    }
+}
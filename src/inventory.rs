@@ -23,6 +23,7 @@ impl Plugin for InventoryPlugin {
                     item_button_interaction,
                     increment_button_interaction,
                     use_button_interaction,
+                    update_player_level_text,
                 )
                     .in_set(OnUpdate(UIState::Inventory)),
             );
@@ -63,12 +64,43 @@ impl ItemStack {
         }
     }
 
+    /// What using one of the item does
+    pub fn effect(&self) -> ItemEffect {
+        match self.item_id {
+            0 => ItemEffect::GainXp(10),
+            1 => ItemEffect::GainXp(100),
+            2 => ItemEffect::GainXp(2000),
+            3 => ItemEffect::Heal(0.1),
+            4 => ItemEffect::Heal(0.25),
+            _ => unreachable!(),
+        }
+    }
+
     pub fn add(&mut self, quantity: u8) {
         assert!(self.quantity + quantity <= 32);
         self.quantity += quantity;
     }
 }
 
+/// What using an item does. Items used in a fight take up one of the player's action phases, see
+/// [`CombatAction::UseItem`]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ItemEffect {
+    GainXp(u32),
+    /// Heals this fraction of max HP
+    Heal(f32),
+}
+
+impl ItemEffect {
+    /// Applies the effect's healing, if it has any. Returns how much was healed
+    pub fn heal(&self, health: &mut Health) -> f32 {
+        match *self {
+            ItemEffect::Heal(fraction) => health.heal(health.max_hp * fraction),
+            ItemEffect::GainXp(_) => 0.0,
+        }
+    }
+}
+
 #[derive(Resource, Serialize, Deserialize)]
 pub struct Inventory {
     slots: Vec<Option<ItemStack>>,
@@ -139,6 +171,7 @@ fn handle_keys(
     ui_state: Res<State<UIState>>,
     mut next_ui_state: ResMut<NextState<UIState>>,
     mut gi_lock_sender: EventWriter<GlobalInteractionLockEvent>,
+    opt_combat_manager: Option<Res<CombatManager>>,
 ) {
     if keys.just_pressed(KeyCode::Escape) {
         match ui_state.0 {
@@ -150,6 +183,10 @@ fn handle_keys(
                 next_ui_state.set(UIState::Null);
                 gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked));
             }
+            UIState::WorldMap | UIState::Character => {
+                next_ui_state.set(UIState::Null);
+                return;
+            }
             UIState::Null => {}
         }
     }
@@ -161,11 +198,34 @@ fn handle_keys(
             _ => {}
         }
     }
+
+    // Stat points can't be spent in the middle of a fight
+    if keys.just_pressed(KeyCode::C) {
+        match ui_state.0 {
+            UIState::Null if opt_combat_manager.is_none() => next_ui_state.set(UIState::Character),
+            UIState::Character => next_ui_state.set(UIState::Null),
+            _ => {}
+        }
+    }
+
+    if keys.just_pressed(KeyCode::M) {
+        match ui_state.0 {
+            UIState::Null => next_ui_state.set(UIState::WorldMap),
+            UIState::WorldMap => next_ui_state.set(UIState::Null),
+            _ => {}
+        }
+    }
 }
 
 #[derive(Component)]
 struct InventoryUIRoot;
 
+#[derive(Component)]
+struct PlayerLevelText;
+
+#[derive(Component)]
+struct PlayerXpText;
+
 #[derive(Component, Reflect)]
 struct InventoryItemButton {
     slot_idx: usize,
@@ -299,7 +359,8 @@ fn draw_inventory(
                                             ),
                                             ..default()
                                         })
-                                        .insert(Name::new("Text"));
+                                        .insert(Name::new("Text"))
+                                        .insert(PlayerLevelText);
                                     // Player XP Text
                                     commands
                                         .spawn(TextBundle {
@@ -317,7 +378,8 @@ fn draw_inventory(
                                             ),
                                             ..default()
                                         })
-                                        .insert(Name::new("Text 2"));
+                                        .insert(Name::new("Text 2"))
+                                        .insert(PlayerXpText);
                                 });
 
                             // Inventory grid container
@@ -792,6 +854,10 @@ fn use_button_interaction(
         Query<&mut UiImage, With<ItemStatsImage>>,
     )>,
     asset_server: Res<AssetServer>,
+    mut xp_sender: EventWriter<GainXp>,
+    mut player: Query<&mut Player>,
+    opt_combat_manager: Option<Res<CombatManager>>,
+    mut combat_actions: EventWriter<CombatAction>,
 ) {
     let target_slot_idx = item_buttons
         .iter()
@@ -805,16 +871,38 @@ fn use_button_interaction(
     for (interaction, mut background_colour) in interaction_query.iter_mut() {
         match interaction {
             Interaction::Clicked => {
-                let amount = select_qty.get_single().unwrap().quantity;
                 let target_slot_idx = target_slot_idx.unwrap();
                 // Safe to unwrap - guaranteed that a selected slot exists inside the inventory slots
+                let effect = inventory.slots[target_slot_idx].as_ref().unwrap().effect();
+                let amount = match opt_combat_manager {
+                    // Using an item takes up one of the player's action phases, so only one can be used at a time
+                    Some(ref combat_manager) => {
+                        let action = CombatAction::UseItem(effect);
+                        if !combat_manager.state.is_legal(&action) {
+                            continue;
+                        }
+                        combat_actions.send(action);
+                        1
+                    }
+                    None => {
+                        let amount = select_qty.get_single().unwrap().quantity;
+                        let mut player = player.single_mut();
+                        for _ in 0..amount {
+                            effect.heal(&mut player.health);
+                        }
+                        let player_data = serde_json::to_string(&*player).unwrap();
+                        fs::write("player_data.json", player_data)
+                            .expect("It is very difficult for this error to occur. Stop messing with the code");
+                        amount
+                    }
+                };
+                if let ItemEffect::GainXp(xp) = effect {
+                    xp_sender.send(GainXp(xp * amount as u32));
+                }
                 if inventory.slots[target_slot_idx].as_ref().unwrap().quantity - amount > 0 {
                     // Didn't use the entire stack
                     inventory.slots[target_slot_idx].as_mut().unwrap().quantity -= amount;
 
-                    // !TODO: Apply appropriate effects upon removing items from inventory
-                    // (currently does nothing apart from remove)
-
                     // Update qty text to new value
                     for (mut qty_text, qty_text_comp) in texts.p3().iter_mut() {
                         if qty_text_comp.slot_idx == target_slot_idx {
@@ -864,3 +952,53 @@ fn use_button_interaction(
     fs::write("inventory.json", inventory_data)
         .expect("It is very difficult for this error to occur. Stop messing with the code");
 }
+
+fn update_player_level_text(
+    player: Query<&Player>,
+    level_curve: Res<LevelCurve>,
+    mut level_text: Query<&mut Text, (With<PlayerLevelText>, Without<PlayerXpText>)>,
+    mut xp_text: Query<&mut Text, With<PlayerXpText>>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    for mut text in &mut level_text {
+        text.sections[0].value = format!("Player Level: {}", player.level);
+    }
+    for mut text in &mut xp_text {
+        text.sections[0].value = format!("Player XP: {}/{}", player.xp, level_curve.xp_to_next(player.level));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn potions_never_heal_past_max_hp() {
+        let mut health = Health {
+            max_hp: 100.0,
+            hp: 95.0,
+        };
+        assert_eq!(ItemStack::new(3, 1).effect().heal(&mut health), 5.0);
+        assert_eq!(health.hp, 100.0);
+
+        health.hp = 10.0;
+        assert_eq!(ItemStack::new(4, 1).effect().heal(&mut health), 25.0);
+        assert_eq!(health.hp, 35.0);
+        for _ in 0..5 {
+            ItemStack::new(4, 1).effect().heal(&mut health);
+        }
+        assert_eq!(health.hp, health.max_hp);
+    }
+
+    #[test]
+    fn xp_items_dont_heal() {
+        let mut health = Health {
+            max_hp: 100.0,
+            hp: 50.0,
+        };
+        assert_eq!(ItemStack::new(1, 1).effect().heal(&mut health), 0.0);
+        assert_eq!(health.hp, 50.0);
+    }
+}
@@ -0,0 +1,269 @@
+use std::fs;
+
+use crate::*;
+
+/// Plugin responsible for the player's XP and levels.
+///
+/// XP is handed out with [`GainXp`], for killing enemies and for using XP items. How much XP every level takes and
+/// what it's worth comes from the [`LevelCurve`] in assets/levels.json. Levelling up gives the player stat points,
+/// which are spent on the character sheet, toggled with [C] outside of fights.
+pub struct LevellingPlugin;
+
+impl Plugin for LevellingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelCurve>()
+            .add_event::<GainXp>()
+            .add_system(
+                award_kill_xp
+                    .after(present_combat_events)
+                    .run_if(resource_exists::<CombatManager>()),
+            )
+            .add_system(gain_xp.after(award_kill_xp).in_set(OnUpdate(GameState::InGame)))
+            .add_system(save_player_after_combat.run_if(resource_removed::<CombatManager>()))
+            .add_system(
+                draw_character_sheet
+                    .in_schedule(OnEnter(UIState::Character))
+                    .in_set(OnUpdate(GameState::InGame)),
+            )
+            .add_system(undraw_character_sheet.in_schedule(OnExit(UIState::Character)))
+            .add_system(spend_stat_points.in_set(OnUpdate(UIState::Character)));
+    }
+}
+
+/// How much XP every level takes, and what levelling up is worth. Loaded from assets/levels.json
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+pub struct LevelCurve {
+    /// XP it takes to get from level 1 to level 2
+    pub base_xp: u32,
+    /// Every level takes this many times as much XP as the one before it
+    pub growth: f32,
+    /// XP stops counting once the player gets here
+    pub max_level: u32,
+    pub stat_points_per_level: u32,
+    /// XP for killing an enemy, for every point of its max HP
+    pub xp_per_enemy_hp: f32,
+}
+
+impl Default for LevelCurve {
+    fn default() -> Self {
+        let curve = fs::read_to_string("assets/levels.json").expect("Something went wrong reading the file");
+        serde_json::from_str(&curve).unwrap()
+    }
+}
+
+impl LevelCurve {
+    /// XP it takes to get from `level` to the next one
+    pub fn xp_to_next(&self, level: u32) -> u32 {
+        let xp = self.base_xp as f32 * self.growth.powi(level as i32 - 1);
+        (xp.round() as u32).max(1)
+    }
+
+    /// XP for killing an enemy with `max_hp`
+    pub fn kill_xp(&self, max_hp: f32) -> u32 {
+        (max_hp * self.xp_per_enemy_hp).round() as u32
+    }
+
+    /// Gives the player XP, levelling them up as many times as it's enough for. Returns how many levels they gained
+    pub fn award(&self, player: &mut Player, xp: u32) -> u32 {
+        let mut levels = 0;
+        player.xp += xp;
+        while player.level < self.max_level && player.xp >= self.xp_to_next(player.level) {
+            player.xp -= self.xp_to_next(player.level);
+            player.level += 1;
+            player.stat_points += self.stat_points_per_level;
+            levels += 1;
+        }
+        if player.level >= self.max_level {
+            player.xp = 0;
+        }
+        levels
+    }
+}
+
+/// The level a new character starts at
+pub fn first_level() -> u32 {
+    1
+}
+
+/// Gives the player this much XP
+pub struct GainXp(pub u32);
+
+/// Hands out XP for every enemy killed, going by how tough it was
+pub fn award_kill_xp(
+    mut combat_events: EventReader<CombatEvent>,
+    combat_manager: Res<CombatManager>,
+    level_curve: Res<LevelCurve>,
+    mut xp_sender: EventWriter<GainXp>,
+) {
+    for event in combat_events.iter() {
+        let CombatEvent::Died { unit } = event else {
+            continue;
+        };
+        let unit = combat_manager.state.unit(*unit);
+        if unit.faction == Faction::Enemy {
+            xp_sender.send(GainXp(level_curve.kill_xp(unit.health.max_hp)));
+        }
+    }
+}
+
+pub fn gain_xp(
+    mut xp_gained: EventReader<GainXp>,
+    mut player: Query<&mut Player>,
+    level_curve: Res<LevelCurve>,
+    opt_combat_manager: Option<Res<CombatManager>>,
+) {
+    let xp: u32 = xp_gained.iter().map(|event| event.0).sum();
+    let Ok(mut player) = player.get_single_mut() else {
+        return;
+    };
+    if xp == 0 {
+        return;
+    }
+    info!("Gained {} XP", xp);
+    if level_curve.award(&mut player, xp) > 0 {
+        info!(
+            "Reached level {}, with {} stat points to spend",
+            player.level, player.stat_points
+        );
+    }
+    // XP gained in a fight is saved once it's over, see save_player_after_combat
+    if opt_combat_manager.is_none() {
+        let player_data = serde_json::to_string(&*player).unwrap();
+        fs::write("player_data.json", player_data)
+            .expect("It is very difficult for this error to occur. Stop messing with the code");
+    }
+}
+
+/// Saves the XP, levels and stat points the player gained in a fight as soon as it's over
+pub fn save_player_after_combat(player: Query<&Player>) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let player_data = serde_json::to_string(player).unwrap();
+    fs::write("player_data.json", player_data)
+        .expect("It is very difficult for this error to occur. Stop messing with the code");
+}
+
+/// The root of the character sheet, holding the player's stats from when it was opened. Points spent since can be
+/// taken back, but not the ones spent before
+#[derive(Component)]
+pub struct CharacterSheet {
+    pub opened_with: Stats,
+}
+
+#[derive(Component)]
+pub struct StatPointsText;
+
+fn draw_character_sheet(mut commands: Commands, player: Query<&Player>, asset_server: Res<AssetServer>) {
+    let player = player.single();
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.9).into(),
+            ..default()
+        })
+        .insert(Name::new("CharacterSheetUIRoot"))
+        .insert(CharacterSheet {
+            opened_with: player.stats.clone(),
+        })
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle::from_section(
+                    format!("Level {}", player.level),
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                ))
+                .insert(StatPointsText);
+
+            // The same buttons the stats were first picked with
+            create_stat_ui(parent, &asset_server, Stat::Speed);
+            create_stat_ui(parent, &asset_server, Stat::Damage);
+            create_stat_ui(parent, &asset_server, Stat::Health);
+        });
+}
+
+fn undraw_character_sheet(mut commands: Commands, ui_root: Query<Entity, With<CharacterSheet>>) {
+    commands.entity(ui_root.single()).despawn_recursive();
+}
+
+/// Spends a stat point on every + clicked, and takes back one spent since the sheet was opened for every -. Every
+/// change is saved to player_data.json
+#[allow(clippy::type_complexity)]
+fn spend_stat_points(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &ModifyStatButton),
+        (With<Button>, Changed<Interaction>),
+    >,
+    mut player: Query<&mut Player>,
+    sheet: Query<&CharacterSheet>,
+    mut stat_texts: Query<(&mut Text, &Stat), Without<StatPointsText>>,
+    mut points_text: Query<&mut Text, With<StatPointsText>>,
+) {
+    let (Ok(mut player), Ok(sheet)) = (player.get_single_mut(), sheet.get_single()) else {
+        return;
+    };
+
+    for (interaction, mut background_color, modify_stat_button) in &mut interaction_query {
+        match interaction {
+            Interaction::Clicked => {
+                let stat = modify_stat_button.stat_type;
+                let change = modify_stat_button.modification_value;
+                if player.stats.get(stat) + change >= sheet.opened_with.get(stat) && player.stat_points as i32 >= change
+                {
+                    let mut stats = player.stats.clone();
+                    *stats.get_mut(stat) += change;
+                    player.stat_points = (player.stat_points as i32 - change) as u32;
+                    player.set_stats(stats);
+                    // The sheet can only be opened outside of fights, so the player is saved straight away
+                    let player_data = serde_json::to_string(&*player).unwrap();
+                    fs::write("player_data.json", player_data)
+                        .expect("It is very difficult for this error to occur. Stop messing with the code");
+                }
+            }
+            Interaction::Hovered => *background_color = HOVERED_BUTTON.into(),
+            Interaction::None => *background_color = NORMAL_BUTTON.into(),
+        }
+    }
+
+    for (mut text, &stat) in &mut stat_texts {
+        text.sections[0].value = format!("{:?}: {}", stat, player.stats.get(stat));
+    }
+    for mut text in &mut points_text {
+        text.sections[0].value = format!(
+            "Level {} - {} stat points to spend, {} max HP",
+            player.level, player.stat_points, player.health.max_hp
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leftover_xp_carries_over_to_the_next_level() {
+        let curve = LevelCurve {
+            base_xp: 100,
+            growth: 2.0,
+            max_level: 4,
+            stat_points_per_level: 2,
+            xp_per_enemy_hp: 1.0,
+        };
+        let mut player = Player::new(0, 0, (1, 1, 1));
+        assert_eq!(curve.award(&mut player, 350), 2);
+        assert_eq!((player.level, player.xp, player.stat_points), (3, 50, 4));
+
+        assert_eq!(curve.award(&mut player, 10_000), 1);
+        assert_eq!((player.level, player.xp), (4, 0));
+    }
+}
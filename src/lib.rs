@@ -4,29 +4,70 @@ use std::f32::consts::PI;
 use std::fs;
 
 use bevy::prelude::*;
-use bevy_mod_picking::prelude::RaycastPickCamera;
 use bevy_scene_hook::{HookedSceneBundle, SceneHook};
 
+pub mod abilities;
 pub mod animengine;
 pub mod astar;
 pub mod character_creation;
 pub mod chest;
+pub mod chunks;
 pub mod combat;
+pub mod combat_core;
+pub mod combat_log;
+pub mod damage;
+pub mod encounters;
+pub mod environment;
+pub mod fasttravel;
+pub mod fog;
+pub mod highlight;
+pub mod initiative;
+pub mod interaction;
 pub mod inventory;
+pub mod levelling;
 pub mod load;
 pub mod map_load;
+pub mod movement;
+pub mod picking;
+pub mod props;
+pub mod roaming;
+pub mod sign;
+pub mod status_effects;
 pub mod tempui;
+pub mod tile_assets;
+pub mod worldmap;
 
+pub use abilities::*;
 pub use animengine::*;
 pub use astar::*;
 pub use character_creation::*;
 pub use chest::*;
+pub use chunks::*;
 pub use combat::*;
+pub use combat_core::*;
+pub use combat_log::*;
+pub use damage::*;
+pub use encounters::*;
+pub use environment::*;
+pub use fasttravel::*;
+pub use fog::*;
+pub use highlight::*;
+pub use initiative::*;
+pub use interaction::*;
 pub use inventory::*;
+pub use levelling::*;
 pub use load::*;
 pub use map_load::*;
+pub use movement::*;
+pub use picking::*;
+pub use props::*;
+pub use roaming::*;
 use serde::{Deserialize, Serialize};
+pub use sign::*;
+pub use status_effects::*;
 pub use tempui::*;
+pub use tile_assets::*;
+pub use worldmap::*;
 
 #[derive(States, Reflect, PartialEq, Eq, Debug, Clone, Hash, Default)]
 pub enum GameState {
@@ -49,6 +90,9 @@ pub enum GameState {
 pub enum UIState {
     Inventory,
     OpenChest,
+    WorldMap,
+    /// Spending stat points, see [`LevellingPlugin`]
+    Character,
     #[default]
     Null,
 }
@@ -56,59 +100,66 @@ pub enum UIState {
 #[derive(Component, Reflect, Serialize, Deserialize)]
 pub struct Player {
     pub hex_coord: HexCoord,
-    pub path: Option<Vec<HexCoord>>,
     pub health: Health,
     pub respawn_point: RespawnPoint,
     // Used for force ending combat phase if player is out of movement
     pub remaining_speed: i32,
-    #[serde(default, skip)]
-    pub move_timer: Timer,
     pub stats: Stats,
     pub energy: i32,
+    /// Waypoints the player has discovered and can fast travel between
+    #[serde(default)]
+    pub waypoints: Vec<Waypoint>,
+    /// IDs of the abilities the player can use in combat, see [`AbilityBook`]
+    #[serde(default = "default_abilities")]
+    pub abilities: Vec<String>,
+    #[serde(default = "player_damage_stats")]
+    pub damage_stats: DamageStats,
+    #[serde(default = "first_level")]
+    pub level: u32,
+    /// XP gained towards the next level, see [`LevelCurve`]
+    #[serde(default)]
+    pub xp: u32,
+    /// Gained by levelling up, and spent on stats
+    #[serde(default)]
+    pub stat_points: u32,
 }
 
 impl Player {
-    /// `move_timer_duration`: The duration of the running animation of the REntity
-    /// Access like so:
-    /// ```
-    /// fn my_system(re_map: Res<REntityMap>) {
-    ///     let run_dur = re_map.0.get(&REntityType::Kraug).unwrap().animations[9].duration;
-    /// }
-    /// ```
-    pub fn new(q: i32, r: i32, move_timer_duration: f32, stats: (i32, i32, i32)) -> Player {
-        // Set it close to the just finished amount so we can snap straight into animations during movement
-        let mut timer = Timer::from_seconds(move_timer_duration, TimerMode::Repeating);
-        timer.set_elapsed(::std::time::Duration::from_secs_f32(move_timer_duration - 0.05));
-
+    pub fn new(q: i32, r: i32, stats: (i32, i32, i32)) -> Player {
+        let stats = Stats {
+            speed: stats.0,
+            damage: stats.1,
+            health: stats.2,
+        };
         Player {
             hex_coord: HexCoord::new(q, r),
-            path: None,
-            // move_timer: Timer::from_seconds(move_timer_duration, TimerMode::Repeating),
-            move_timer: timer,
-            health: Health::new(((stats.2 + 5) * 10) as f32),
+            health: Health::new(stats.max_hp()),
             respawn_point: RespawnPoint {
                 world: "1".to_string(),
                 coord: HexCoord::new(0, 0),
             },
-            remaining_speed: stats.0,
-            stats: Stats {
-                speed: stats.0,
-                damage: stats.1,
-                health: stats.2,
-            },
+            remaining_speed: stats.speed,
+            stats,
             energy: 0,
+            waypoints: vec![],
+            abilities: default_abilities(),
+            damage_stats: player_damage_stats(),
+            level: first_level(),
+            xp: 0,
+            stat_points: 0,
         }
     }
 
-    /// "Resetting" the move timer is setting the elapsed to a split second before just_finished()
-    /// triggers, as we want movement animations to run ASAP
-    pub fn reset_move_timer(&mut self) {
-        self.move_timer
-            .set_elapsed(self.move_timer.duration() - ::std::time::Duration::from_secs_f32(0.05));
+    /// Changes the player's stats, recalculating their max HP. HP they've lost stays lost
+    pub fn set_stats(&mut self, stats: Stats) {
+        let lost = self.health.max_hp - self.health.hp;
+        self.health.max_hp = stats.max_hp();
+        self.health.hp = (self.health.max_hp - lost).max(1.0);
+        self.stats = stats;
     }
 }
 
-#[derive(Component, Resource, Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Health {
     pub max_hp: f32,
     pub hp: f32,
@@ -117,6 +168,13 @@ impl Health {
     pub fn new(hp: f32) -> Health {
         Health { max_hp: hp, hp }
     }
+
+    /// Heals up to `amount`, never going over max HP. Returns how much was healed
+    pub fn heal(&mut self, amount: f32) -> f32 {
+        let healed = amount.min(self.max_hp - self.hp).max(0.0);
+        self.hp += healed;
+        healed
+    }
 }
 
 #[derive(Component)]
@@ -138,6 +196,25 @@ pub const HORIZONTAL_SPACING: f32 = 5.2 * SCALE;
 pub const VERTICAL_SPACING: f32 = 4.5 * SCALE;
 pub const HOR_OFFSET: f32 = 2.6 * SCALE;
 
+/// How many times faster than its running animation the player walks between tiles. The animation is sped up to
+/// match
+pub const PLAYER_MOVEMENT_SPEED: f32 = 1.0;
+
+/// World space height of a single step of tile elevation
+pub const ELEVATION_STEP: f32 = 1.5 * SCALE;
+/// The largest height difference that can be crossed in a single step
+pub const MAX_CLIMB: i32 = 1;
+/// Extra movement charged for every step of height climbed
+pub const CLIMB_COST: i32 = 1;
+
+/// How far above the base tile height something standing on a tile of the given elevation is
+pub fn elevation_height(elevation: i32) -> f32 {
+    elevation as f32 * ELEVATION_STEP
+}
+
+pub const WAYPOINT_COLOUR: Color = Color::rgba(0.4, 0.8, 1.0, 1.0);
+pub const CLIFF_COLOUR: Color = Color::rgb(0.35, 0.3, 0.25);
+
 #[derive(States, Reflect, PartialEq, Eq, Debug, Clone, Copy, Hash, Default)]
 /// Global Interaction State
 pub enum GIState {
@@ -152,88 +229,98 @@ pub enum GIState {
 pub struct GlobalInteractionLockEvent(GIState);
 
 pub fn change_gi_state(
-    mut commands: Commands,
     mut gi_lock_event: EventReader<GlobalInteractionLockEvent>,
     mut next_gi_state: ResMut<NextState<GIState>>,
-    raycast_camera: Query<Entity, With<RaycastPickCamera>>,
-    player_camera: Query<Entity, With<PlayerCameraMarker>>,
-    mut player: Query<&mut Player>,
 ) {
-    let raycast_camera = raycast_camera.get_single();
-    let player_camera = player_camera.get_single();
-
+    // Tiles are only picked while unlocked, see pick_tiles
     for event in gi_lock_event.iter() {
-        match event.0 {
-            // Disable raycasting
-            s @ (GIState::Locked | GIState::LockedByMovement) => {
-                next_gi_state.set(s);
-
-                if let Ok(player_camera) = player_camera {
-                    commands.entity(player_camera).remove::<RaycastPickCamera>();
-                }
-            }
-            // Enable raycasting
-            s @ GIState::Unlocked => {
-                next_gi_state.set(s);
-
-                if raycast_camera.is_err() {
-                    if let Ok(player_camera) = player_camera {
-                        commands.entity(player_camera).insert(RaycastPickCamera::default());
-                    }
-                }
-            }
-        }
+        next_gi_state.set(event.0);
     }
 }
 
 pub fn update_tile_state_stable(
-    mut tiles: Query<(&Handle<StandardMaterial>, &mut Tile)>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut gi_lock_sender: EventWriter<GlobalInteractionLockEvent>,
+    mut tile_clicks: EventReader<TileClicked>,
+    tiles: Query<&Tile>,
+    interactables: Query<&Interactable>,
+    mut move_request_sender: EventWriter<MoveRequest>,
     opt_combat_manager: Option<Res<CombatManager>>,
-    player: Query<&Player>,
+    player: Query<(Entity, &Player)>,
+    mut next_ui_state: ResMut<NextState<UIState>>,
 ) {
-    if opt_combat_manager.is_some() {
-        return;
-    }
-    for (material_handle, mut tile) in &mut tiles {
-        let raw_material = materials.get_mut(material_handle).unwrap();
-
-        let mut current_colour = Color::rgba(1.0, 1.0, 1.0, 0.6);
-        if tile.is_hovered {
-            current_colour = Color::BLUE;
-        } else if let Some(ref sub_region_data) = tile.sub_region_id {
-            current_colour.set_a(1.0);
-            match sub_region_data.subregion_type {
-                SubregionType::UnclearedCombat => {
-                    // current_colour.set_r();
-                    current_colour.set_g(0.5);
-                    current_colour.set_b(0.5);
-                }
-                SubregionType::ClearedCombat => {
-                    current_colour.set_r(0.5);
-                    // current_colour.set_g(0.5);
-                    current_colour.set_b(0.5);
-                }
-                SubregionType::Other => {}
-            }
+    for click in tile_clicks.iter() {
+        // Clicks during combat are handled by combat_system
+        if opt_combat_manager.is_some() {
+            continue;
         }
-        if tile.is_obstructed {
-            current_colour = Color::GRAY
+        let Ok((player_entity, player)) = player.get_single() else {
+            continue;
+        };
+        if player.hex_coord == click.coord {
+            // Pressing the waypoint you're standing on brings up the world map for fast travel
+            if tiles.iter().any(|tile| tile.coord == click.coord && tile.is_waypoint) {
+                next_ui_state.set(UIState::WorldMap);
+            }
+            // TODO: Pressed your own tile - inventory?
+        } else if !interactables
+            .iter()
+            .any(|interactable| interactable.hex_coord == click.coord)
+        {
+            // Clicking an interactable walks up to it instead, see request_interactions
+            move_request_sender.send(MoveRequest::new(player_entity, click.coord));
         }
+    }
+}
 
-        raw_material.base_color = current_colour;
+/// Walks the player a single tile at a time with the keyboard.
+///
+/// The keys are laid out like the neighbours of a tile:
+/// ```
+///   Q   W        r-1
+/// A   S   D      r
+///   Z   X        r+1
+/// ```
+/// where S is the player's tile, and moving along a row changes q
+pub fn keyboard_move_player(
+    keys: Res<Input<KeyCode>>,
+    player: Query<(Entity, &Player)>,
+    mut move_request_sender: EventWriter<MoveRequest>,
+    mut combat_actions: EventWriter<CombatAction>,
+    opt_combat_manager: Option<Res<CombatManager>>,
+    ui_state: Res<State<UIState>>,
+) {
+    if ui_state.0 != UIState::Null {
+        return;
+    }
+    let Ok((player_entity, player)) = player.get_single() else {
+        return;
+    };
+    let coord = player.hex_coord;
+    // Odd rows are shifted half a tile along, see HexCoord::to_world
+    let shift = coord.r.rem_euclid(2);
+    let target = if keys.just_pressed(KeyCode::A) {
+        HexCoord::new(coord.q - 1, coord.r)
+    } else if keys.just_pressed(KeyCode::D) {
+        HexCoord::new(coord.q + 1, coord.r)
+    } else if keys.just_pressed(KeyCode::Q) {
+        HexCoord::new(coord.q - 1 + shift, coord.r - 1)
+    } else if keys.just_pressed(KeyCode::W) {
+        HexCoord::new(coord.q + shift, coord.r - 1)
+    } else if keys.just_pressed(KeyCode::Z) {
+        HexCoord::new(coord.q - 1 + shift, coord.r + 1)
+    } else if keys.just_pressed(KeyCode::X) {
+        HexCoord::new(coord.q + shift, coord.r + 1)
+    } else {
+        return;
+    };
 
-        if tile.is_clicked {
-            if let Ok(player) = player.get_single() {
-                if player.hex_coord == tile.coord {
-                    tile.is_clicked = false;
-                    // TODO: Pressed your own tile - inventory?
-                } else {
-                    gi_lock_sender.send(GlobalInteractionLockEvent(GIState::LockedByMovement));
-                }
+    match opt_combat_manager {
+        // Movement during combat comes out of what's left of the player's speed for this turn
+        Some(combat_manager) => {
+            if combat_manager.state.turn == Turn::Player(Phase::Movement) {
+                combat_actions.send(CombatAction::Move(target));
             }
         }
+        None => move_request_sender.send(MoveRequest::new(player_entity, target)),
     }
 }
 
@@ -292,165 +379,83 @@ pub fn rotation_to(player: HexCoord, adjacent: HexCoord) -> f32 {
     }
 }
 
-pub fn move_player_stable(
-    mut tiles: Query<(&Handle<StandardMaterial>, &mut Tile)>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+/// Removes the highlight of the player's path and destination
+fn clear_path_highlight(tiles: &mut Query<(&mut TileHighlight, &Tile)>) {
+    for (mut highlight, _) in tiles {
+        set_highlight(&mut highlight, HighlightLayer::PathPreview, false);
+        set_highlight(&mut highlight, HighlightLayer::Selection, false);
+    }
+}
+
+/// Reacts to the player walking around: locks interaction and highlights the path while they walk, discovers
+/// waypoints, enters subregions and saves where they stopped
+#[allow(clippy::too_many_arguments)]
+pub fn follow_player_movement(
+    mut move_started: EventReader<MoveStarted>,
+    mut step_completed: EventReader<StepCompleted>,
+    mut move_finished: EventReader<MoveFinished>,
+    mut tiles: Query<(&mut TileHighlight, &Tile)>,
     mut gi_lock_sender: EventWriter<GlobalInteractionLockEvent>,
-    mut player_query: Query<(&mut Transform, &mut Player, &mut RiggedEntity)>,
-    // mut camera_query: Query<(&mut Transform, &mut PlayerCameraMarker), Without<Player>>,
-    // mut r_entities: Query<&mut RiggedEntity>,
-    time: Res<Time>,
+    mut player_query: Query<(Entity, &mut Player, &mut HexMover)>,
     mut map_context: ResMut<MapContext>,
-    chests: Query<(Entity, &Chest)>,
-    mut chest_open_sender: EventWriter<ChestOpenEvent>,
     mut combat_manager: Option<ResMut<CombatManager>>,
 ) {
-    let (mut p_transform, mut p, mut p_rentity) = player_query.get_single_mut().unwrap();
-
-    // Calculate only once
-    if p.path.is_none() {
-        let end_tile = tiles.iter_mut().find_map(|(_, mut t)| {
-            if t.is_clicked {
-                t.is_clicked = false;
-                if !t.is_obstructed {
-                    Some(t.coord)
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        });
-        if let Some(end_tile) = end_tile {
-            let start_tile = p.hex_coord;
-
-            let obstructed_tiles: Vec<HexCoord> = tiles
-                .iter()
-                .filter_map(|(_, t)| if t.is_obstructed { Some(t.coord) } else { None })
-                .collect();
-
-            let path = astar(start_tile, end_tile, &obstructed_tiles);
-            // TODO: Clarify the None case for the astar func... I think input has been sanitised by now (assert isn't
-            // panicking so that's a good sign)
-            assert!(path.is_some());
-
-            if let Some(_) = combat_manager {
-                let mut some_path = path.unwrap();
-                some_path.remove(0);
-                while some_path.len() > p.remaining_speed as usize {
-                    some_path.remove(some_path.len() - 1);
-                }
-                p.path = Some(some_path);
-                p.remaining_speed = p.remaining_speed - p.path.as_ref().unwrap().len() as i32;
-            } else {
-                p.path = path;
-            }
+    let Ok((player_entity, mut p, mut mover)) = player_query.get_single_mut() else {
+        return;
+    };
 
-            trace!("path len {}", p.path.as_ref().unwrap().len());
+    for event in move_started.iter().filter(|e| e.entity == player_entity) {
+        gi_lock_sender.send(GlobalInteractionLockEvent(GIState::LockedByMovement));
 
-            // Highlight the path yellow
-            tiles.for_each(|(material_handle, tile)| {
-                if p.path.as_ref().unwrap().contains(&tile.coord) {
-                    materials.get_mut(material_handle).unwrap().base_color = Color::YELLOW.with_a(0.6);
-                }
-            });
-        } else {
-            gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked))
-        }
+        // Highlight the path, and the tile at the end of it
+        tiles.for_each_mut(|(mut highlight, tile)| {
+            if event.path.contains(&tile.coord) {
+                highlight.add(HighlightLayer::PathPreview);
+            }
+            if event.path.last() == Some(&tile.coord) {
+                highlight.add(HighlightLayer::Selection);
+            }
+        });
     }
 
-    // Timer just finished -> Set player transform (calculate by mapping qr to xz) -> Pend animation
-
-    p.move_timer.tick(time.delta());
-    if p.move_timer.just_finished() {
-        let pos = p.hex_coord;
-
-        let curr_tile = tiles.iter().find(|(_, tile)| tile.coord == pos);
-
-        // If the player walks into a subregion tile, terminate pathfind and change the map
-        if let Some((_, tile)) = curr_tile {
-            if let Some(ref subregion_data) = tile.sub_region_id {
-                trace!("Changing subregion!");
-                map_context.change_map(subregion_data.id.clone());
-                p.path = Some(vec![]);
-                p.reset_move_timer();
-                gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked));
+    for event in step_completed.iter().filter(|e| e.entity == player_entity) {
+        let Some((_, tile)) = tiles.iter().find(|(_, tile)| tile.coord == event.to) else {
+            continue;
+        };
+
+        // Walking over a waypoint for the first time registers it for fast travel
+        if tile.is_waypoint && combat_manager.is_none() {
+            let waypoint = Waypoint {
+                world: map_context.id.clone(),
+                coord: tile.coord,
+            };
+            if !p.waypoints.contains(&waypoint) {
+                info!("Discovered a waypoint at coord q={} r={}", tile.coord.q, tile.coord.r);
+                p.waypoints.push(waypoint);
             }
+        }
 
-            // If the player walks into a chest, terminate pathfind, reset things and send an open chest event
-            for (chest_ent, chest) in &chests {
-                if tile.coord == chest.hex_coord {
-                    info!("Opening a chest at coord q={} r={}", tile.coord.q, tile.coord.r);
-                    p.path = Some(vec![]);
-                    p.reset_move_timer();
-
-                    p_transform.translation.x =
-                        tile.coord.q as f32 * HORIZONTAL_SPACING + tile.coord.r as f32 % 2.0 * HOR_OFFSET;
-                    p_transform.translation.z = tile.coord.r as f32 * VERTICAL_SPACING;
-
-                    tiles.for_each(|(material_handle, tile)| {
-                        let mut colour = materials.get_mut(material_handle).unwrap();
-                        if colour.base_color == Color::YELLOW.with_a(0.6) {
-                            if tile.sub_region_id.is_none() {
-                                colour.base_color = Color::rgba(1.0, 1.0, 1.0, 0.6)
-                            } else {
-                                match tile.sub_region_id.as_ref().unwrap().subregion_type {
-                                    SubregionType::Other => colour.base_color = Color::WHITE,
-                                    _ => {}
-                                }
-                            }
-                        }
-                    });
-
-                    chest_open_sender.send(ChestOpenEvent {
-                        chest_ent,
-                        chest: chest.clone(),
-                    });
-
-                    return;
-                }
+        // If the player walks into a subregion tile, stop walking and change the map
+        if let Some(ref subregion_data) = tile.sub_region_id {
+            trace!("Changing subregion!");
+            map_context.change_map(subregion_data.id.clone());
+            mover.stop();
+            if let Some(ref mut combat_manager) = combat_manager {
+                combat_manager.state.commit_moves();
             }
         }
+    }
 
-        if let Some(player_path) = &mut p.path {
-            p_transform.translation.x = pos.q as f32 * HORIZONTAL_SPACING + pos.r as f32 % 2.0 * HOR_OFFSET;
-            p_transform.translation.z = pos.r as f32 * VERTICAL_SPACING;
-
-            if !player_path.is_empty() {
-                let next_tile = player_path.remove(0);
-
-                if p.hex_coord != next_tile {
-                    p_transform.rotation = Quat::from_rotation_y(rotation_to(p.hex_coord, next_tile));
-                    p_rentity.pend(9);
-                }
-
-                p.hex_coord = next_tile;
-            } else {
-                p.path = None;
-                p.reset_move_timer();
-                gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked));
-                if combat_manager.is_none() {
-                    p.respawn_point.coord = p.hex_coord;
-                    p.respawn_point.world = map_context.id.clone();
-                    // let player_serialise_struct: Player = p.into();
-                    let player_data = serde_json::to_string(&*p).unwrap();
-                    fs::write("player_data.json", player_data)
-                        .expect("It is very difficult for this error to occur. Stop messing with the code");
-                }
-                tiles.for_each(|(material_handle, tile)| {
-                    let mut colour = materials.get_mut(material_handle).unwrap();
-                    if colour.base_color == Color::YELLOW.with_a(0.6) {
-                        if tile.sub_region_id.is_none() {
-                            colour.base_color = Color::rgba(1.0, 1.0, 1.0, 0.6)
-                        } else {
-                            match tile.sub_region_id.as_ref().unwrap().subregion_type {
-                                SubregionType::Other => colour.base_color = Color::WHITE,
-                                _ => {}
-                            }
-                        }
-                    }
-                })
-            }
+    for _ in move_finished.iter().filter(|e| e.entity == player_entity) {
+        clear_path_highlight(&mut tiles);
+        gi_lock_sender.send(GlobalInteractionLockEvent(GIState::Unlocked));
+        // Arenas are gone once their fight is over, so the player can't respawn in one
+        if combat_manager.is_none() && !map_context.in_arena() {
+            p.respawn_point.coord = p.hex_coord;
+            p.respawn_point.world = map_context.id.clone();
+            let player_data = serde_json::to_string(&*p).unwrap();
+            fs::write("player_data.json", player_data)
+                .expect("It is very difficult for this error to occur. Stop messing with the code");
         }
     }
 }
@@ -7,19 +7,9 @@ pub fn setup_scene(
     asset_server: Res<AssetServer>,
     mut spawn_entity_event: EventWriter<SpawnEntityEvent>,
 ) {
-    commands.insert_resource(ClearColor(Color::ALICE_BLUE));
     commands.insert_resource(Inventory::default());
 
-    // Lighting to brighten everything up
-    commands.insert_resource(AmbientLight {
-        color: Color::Rgba {
-            red: 1.0,
-            green: 1.0,
-            blue: 1.0,
-            alpha: 1.0,
-        },
-        brightness: 0.6,
-    });
+    // The clear colour and lighting come from the loaded region's environment, see [`EnvironmentPlugin`]
 
     spawn_entity_event.send(SpawnEntityEvent {
         entity_type: REntityType::Kraug,
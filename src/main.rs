@@ -47,11 +47,6 @@ fn main() {
         // .add_plugin(FrameTimeDiagnosticsPlugin::default())
         // .add_plugin(WorldInspectorPlugin::new())
         // .add_plugin(EditorPlugin::default())
-        .insert_resource(ClearColor(Color::ALICE_BLUE))
-        .insert_resource(AmbientLight {
-            color: Color::WHITE,
-            brightness: 0.2,
-        })
         // .add_plugin(bevy_egui::EguiPlugin)
         .add_plugin(LoadingPlugin)
         .add_plugin(MenuPlugin)
@@ -59,6 +54,21 @@ fn main() {
         .add_plugin(AnimEnginePlugin)
         .add_plugin(InventoryPlugin)
         .add_plugin(ChestPlugin)
+        .add_plugin(FogOfWarPlugin)
+        .add_plugin(WorldMapPlugin)
+        .add_plugin(FastTravelPlugin)
+        .add_plugin(EnvironmentPlugin)
+        .add_plugin(PropPlugin)
+        .add_plugin(TileAssetsPlugin)
+        .add_plugin(ChunkPlugin)
+        .add_plugin(HexPickingPlugin)
+        .add_plugin(TileHighlightPlugin)
+        .add_plugin(MovementPlugin)
+        .add_plugin(InteractionPlugin)
+        .add_plugin(SignPlugin)
+        .add_plugin(RoamingPlugin)
+        .add_plugin(EncounterPlugin)
+        .add_plugin(LevellingPlugin)
         // .add_plugin(StateInspectorPlugin::<GameState>::default())
         // .add_plugin(ResourceInspectorPlugin::<MapContext>::default())
         // .add_plugin(ResourceInspectorPlugin::<CombatManager>::default())
@@ -77,14 +87,19 @@ fn main() {
                 // update_health_bar,
                 // update_enemy_health,
                 update_tile_state_stable,
+                keyboard_move_player,
             )
                 // .after(spawn_scene)
                 .in_set(OnUpdate(GameState::InGame))
                 .in_set(OnUpdate(GIState::Unlocked)),
         )
         .insert_resource(MapContext::from_map("1".to_string()))
+        .add_plugin(AbilityPlugin)
         .add_plugin(CombatPlugin)
-        .add_system(move_player_stable.in_set(OnUpdate(GIState::LockedByMovement)))
+        .add_plugin(StatusEffectPlugin)
+        .add_plugin(InitiativePlugin)
+        .add_plugin(CombatLogPlugin)
+        .add_system(follow_player_movement.after(advance_moves))
         .run();
 }
 
@@ -1,6 +1,5 @@
-use std::{f32::consts::PI, fs};
+use std::{collections::HashSet, f32::consts::PI, fs};
 
-use bevy_mod_picking::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use bevy_inspector_egui::prelude::*;
@@ -12,6 +11,8 @@ pub struct MapContext {
     pub id: String,
     pub load_new_region: bool,
     pub current_map: Region,
+    /// Where to place the player when the next region loads, instead of the region's spawn spot
+    pub spawn_override: Option<HexCoord>,
 }
 impl MapContext {
     pub fn from_map(id: String) -> Self {
@@ -19,6 +20,7 @@ impl MapContext {
             id: id.clone(),
             load_new_region: true,
             current_map: load_new_map_data(id),
+            spawn_override: None,
         }
     }
     pub fn change_map(&mut self, new_map_id: String) {
@@ -31,11 +33,18 @@ impl MapContext {
         let mut deserialized: HashMap<String, Region> = serde_json::from_str(&contents).unwrap();
         let split_id = self.id.split(".").collect::<Vec<&str>>();
         let prev_id = split_id[..split_id.len() - 1].join(".");
-        let previous_region = deserialized.get_mut(&prev_id).unwrap();
-        for tile in &mut previous_region.tiles {
-            if let Some(ref mut sub_data) = tile.sub_region_id {
-                if sub_data.id == self.id {
-                    tile.sub_region_id = None
+        if let Some(origin) = deserialized.get(&self.id).unwrap().arena.clone() {
+            // Fights on the spot leave nothing behind, and the player goes back to where they were
+            deserialized.remove(&self.id);
+            self.spawn_override = Some(origin.return_coord);
+        } else {
+            deserialized.get_mut(&self.id).unwrap().cleared = true;
+            let previous_region = deserialized.get_mut(&prev_id).unwrap();
+            for tile in &mut previous_region.tiles {
+                if let Some(ref mut sub_data) = tile.sub_region_id {
+                    if sub_data.id == self.id {
+                        tile.sub_region_id = None
+                    }
                 }
             }
         }
@@ -53,6 +62,105 @@ impl MapContext {
         let serialised = serde_json::to_string(&deserialized).unwrap();
         fs::write("world.json", serialised).expect("Unable to write to file");
     }
+    /// Remembers that the player has been to the current region, for the world map
+    pub fn mark_visited(&mut self) {
+        let contents = fs::read_to_string("world.json").expect("Something went wrong reading the file");
+        let mut deserialized: HashMap<String, Region> = serde_json::from_str(&contents).unwrap();
+        let curr_region = deserialized.get_mut(&self.id).unwrap();
+        // Arenas only exist for the length of a fight, so they don't belong on the world map
+        if curr_region.visited || curr_region.arena.is_some() {
+            return;
+        }
+        curr_region.visited = true;
+        let serialised = serde_json::to_string(&deserialized).unwrap();
+        fs::write("world.json", serialised).expect("Unable to write to file");
+    }
+    /// Removes a roaming group from the current region, once it has been engaged
+    pub fn remove_roaming_group(&mut self, group_id: &str) {
+        let contents = fs::read_to_string("world.json").expect("Something went wrong reading the file");
+        let mut deserialized: HashMap<String, Region> = serde_json::from_str(&contents).unwrap();
+        let curr_region = deserialized.get_mut(&self.id).unwrap();
+        curr_region.roaming_groups.retain(|group| group.id != group_id);
+        let serialised = serde_json::to_string(&deserialized).unwrap();
+        fs::write("world.json", serialised).expect("Unable to write to file");
+    }
+    /// Sends the player into a combat subregion to fight `enemies`, instead of the enemies it was made with
+    pub fn start_subregion_fight(&mut self, region_id: &str, enemies: Vec<Enemy>) {
+        let contents = fs::read_to_string("world.json").expect("Something went wrong reading the file");
+        let mut deserialized: HashMap<String, Region> = serde_json::from_str(&contents).unwrap();
+        let combat_region = deserialized.get_mut(region_id).unwrap();
+        combat_region.enemies = Some(enemies);
+        combat_region.cleared = false;
+        let serialised = serde_json::to_string(&deserialized).unwrap();
+        fs::write("world.json", serialised).expect("Unable to write to file");
+        self.change_map(region_id.to_string());
+    }
+    /// Starts a fight against `enemies` on the spot, in an arena made of the current region's tiles around `centre`.
+    /// The enemies are placed around `centre`, and the player starts on `player_coord` and is put back there once the
+    /// fight has been won
+    pub fn start_arena(&mut self, centre: HexCoord, player_coord: HexCoord, enemies: Vec<Enemy>) {
+        let contents = fs::read_to_string("world.json").expect("Something went wrong reading the file");
+        let mut deserialized: HashMap<String, Region> = serde_json::from_str(&contents).unwrap();
+        let curr_region = deserialized.get(&self.id).unwrap();
+
+        // The edge of the arena is walled off so nobody can walk out of it
+        let tiles: Vec<Tile> = curr_region
+            .tiles
+            .iter()
+            .filter(|tile| hex_distance(&tile.coord, &centre) <= ARENA_RADIUS)
+            .map(|tile| Tile {
+                is_obstructed: tile.is_obstructed || hex_distance(&tile.coord, &centre) == ARENA_RADIUS,
+                sub_region_id: None,
+                is_waypoint: false,
+                is_explored: true,
+                ..tile.clone()
+            })
+            .collect();
+        let enemies = place_enemies(enemies, centre, player_coord, &tiles);
+
+        let arena = Region {
+            tiles,
+            enemies: Some(enemies),
+            player_spawn_spot: player_coord,
+            chests: None,
+            environment: curr_region.environment.clone(),
+            visited: false,
+            cleared: false,
+            roaming_groups: vec![],
+            encounters: None,
+            arena: Some(ArenaOrigin {
+                return_coord: player_coord,
+            }),
+        };
+        let arena_id = self.arena_id();
+        deserialized.insert(arena_id.clone(), arena);
+        let serialised = serde_json::to_string(&deserialized).unwrap();
+        fs::write("world.json", serialised).expect("Unable to write to file");
+        self.change_map(arena_id);
+    }
+    /// Regions only get one arena at a time, which is replaced by the next fight on the spot
+    fn arena_id(&self) -> String {
+        format!("{}.{}", self.id, ARENA_ID)
+    }
+    pub fn in_arena(&self) -> bool {
+        self.id.ends_with(&format!(".{}", ARENA_ID))
+    }
+    /// Remembers that the player has seen these tiles in a region. Regions that no longer exist, like arenas that
+    /// have been won, are left alone
+    pub fn mark_explored(region_id: &str, coords: &HashSet<HexCoord>) {
+        let contents = fs::read_to_string("world.json").expect("Something went wrong reading the file");
+        let mut deserialized: HashMap<String, Region> = serde_json::from_str(&contents).unwrap();
+        let Some(curr_region) = deserialized.get_mut(region_id) else {
+            return;
+        };
+        for tile in &mut curr_region.tiles {
+            if coords.contains(&tile.coord) {
+                tile.is_explored = true;
+            }
+        }
+        let serialised = serde_json::to_string(&deserialized).unwrap();
+        fs::write("world.json", serialised).expect("Unable to write to file");
+    }
 }
 
 #[derive(Serialize, Deserialize, Reflect, Default, Debug)]
@@ -61,6 +169,61 @@ pub struct Region {
     pub enemies: Option<Vec<Enemy>>,
     pub player_spawn_spot: HexCoord,
     pub chests: Option<Vec<Chest>>,
+    /// Lighting, clear colour and fog. Regions without one use [`EnvironmentSettings::default`]
+    #[serde(default)]
+    pub environment: Option<EnvironmentSettings>,
+    /// Whether the player has ever entered this region
+    #[serde(default)]
+    pub visited: bool,
+    /// Whether every enemy in this region has been defeated
+    #[serde(default)]
+    pub cleared: bool,
+    #[serde(default)]
+    pub roaming_groups: Vec<RoamingGroup>,
+    /// Random encounters rolled as the player walks around, see [`EncounterPlugin`]
+    #[serde(default)]
+    pub encounters: Option<EncounterTable>,
+    /// Set for arenas built for a fight on the spot, see [`MapContext::start_arena`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arena: Option<ArenaOrigin>,
+}
+
+/// Last part of the ID of an arena region, after the ID of the region it was built in
+pub const ARENA_ID: &str = "arena";
+/// How far from its centre an arena reaches, in tiles
+pub const ARENA_RADIUS: i32 = 4;
+
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub struct ArenaOrigin {
+    /// Where the player is put back once the fight has been won
+    pub return_coord: HexCoord,
+}
+
+/// Puts each enemy on the free tile closest to `centre`, leaving the player some room. Enemies that don't fit are left
+/// out
+pub fn place_enemies(enemies: Vec<Enemy>, centre: HexCoord, player_coord: HexCoord, tiles: &[Tile]) -> Vec<Enemy> {
+    let mut free_tiles: Vec<HexCoord> = tiles
+        .iter()
+        .filter(|tile| !tile.is_obstructed && hex_distance(&tile.coord, &player_coord) > 1)
+        .map(|tile| tile.coord)
+        .collect();
+    free_tiles.sort_by_key(|coord| hex_distance(coord, &centre));
+
+    if enemies.len() > free_tiles.len() {
+        warn!(
+            "Only {} of {} enemies fit in the arena",
+            free_tiles.len(),
+            enemies.len()
+        );
+    }
+    enemies
+        .into_iter()
+        .zip(free_tiles)
+        .map(|(enemy, coord)| Enemy {
+            hex_coord: coord,
+            ..enemy
+        })
+        .collect()
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Reflect, FromReflect)]
@@ -91,18 +254,19 @@ pub fn update_world(
     mut commands: Commands,
     mut map_context: ResMut<MapContext>,
     // mut combat_manager: ResMut<CombatManager>,
-    tiles_query: Query<Entity, With<Tile>>,
+    chunks_query: Query<Entity, With<Chunk>>,
     enemies_query: Query<Entity, With<Enemy>>,
     chests_query: Query<Entity, With<Chest>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    roaming_groups_query: Query<Entity, With<RoamingEnemyGroup>>,
+    tile_assets: Res<TileAssets>,
     mut player_data_query: Query<(&mut Player, &mut Transform)>,
     asset_server: Res<AssetServer>,
+    mut region_chunks: ResMut<RegionChunks>,
 ) {
     if map_context.load_new_region {
         map_context.load_new_region = false;
-        for tile in &tiles_query {
-            commands.entity(tile).despawn_recursive()
+        for chunk in &chunks_query {
+            commands.entity(chunk).despawn_recursive()
         }
         for enemy in &enemies_query {
             commands.entity(enemy).despawn_recursive()
@@ -110,105 +274,49 @@ pub fn update_world(
         for chest in &chests_query {
             commands.entity(chest).despawn_recursive();
         }
+        for group in &roaming_groups_query {
+            commands.entity(group).despawn_recursive();
+        }
         let region = load_new_map_data(map_context.id.clone());
+        map_context.mark_visited();
+        commands.insert_resource(RegionEnvironment(region.environment.clone().unwrap_or_default()));
+        commands.insert_resource(RegionEncounters(region.encounters.clone()));
+        let spawn_spot = map_context.spawn_override.take().unwrap_or(region.player_spawn_spot);
+        let elevations: HashMap<HexCoord, i32> = region.tiles.iter().map(|t| (t.coord, t.elevation)).collect();
+        let elevation_at = |coord: &HexCoord| elevation_height(*elevations.get(coord).unwrap_or(&0));
         let mut data = player_data_query.get_single_mut();
         if let Ok((mut player_data, mut player_transform)) = data {
-            player_data.hex_coord = region.player_spawn_spot;
-            player_transform.translation.x = region.player_spawn_spot.q as f32 * HORIZONTAL_SPACING
-                + region.player_spawn_spot.r as f32 % 2.0 * HOR_OFFSET;
-            player_transform.translation.z = region.player_spawn_spot.r as f32 * VERTICAL_SPACING;
+            player_data.hex_coord = spawn_spot;
+            let position = spawn_spot.to_world();
+            player_transform.translation = Vec3::new(position.x, 1.0 + elevation_at(&spawn_spot), position.y);
         }
 
-        for tile in region.tiles {
-            let mut current_colour = Color::rgba(1.0, 1.0, 1.0, 0.6);
-            if let Some(ref sub_region_data) = tile.sub_region_id {
-                current_colour.set_a(1.0);
-                match sub_region_data.subregion_type {
-                    SubregionType::UnclearedCombat => {
-                        // current_colour.set_r();
-                        current_colour.set_g(0.5);
-                        current_colour.set_b(0.5);
-                    }
-                    SubregionType::ClearedCombat => {
-                        current_colour.set_r(0.5);
-                        // current_colour.set_g(0.5);
-                        current_colour.set_b(0.5);
-                    }
-                    SubregionType::Other => {}
-                }
-            }
-            if tile.is_obstructed {
-                current_colour = Color::GRAY
-            }
-            commands.spawn((
-                PbrBundle {
-                    mesh: meshes.add(Mesh::from(shape::RegularPolygon {
-                        radius: 5.2 * SCALE,
-                        sides: 6,
-                    })),
-                    material: materials.add(current_colour.into()),
-                    transform: Transform::from_scale(Vec3::splat(SCALE))
-                        .with_translation(Vec3::new(
-                            HORIZONTAL_SPACING * tile.coord.q as f32 + tile.coord.r as f32 % 2.0 * HOR_OFFSET,
-                            1.0,
-                            VERTICAL_SPACING * tile.coord.r as f32,
-                        ))
-                        .with_rotation(Quat::from_axis_angle(Vec3 { x: 1.0, y: 0.0, z: 0.0 }, -PI / 2.0)),
-                    ..Default::default()
-                },
-                tile,
-                PickableBundle::default(),
-                RaycastPickTarget::default(),
-                OnPointer::<Over>::target_component_mut::<Tile>(|_, tile| {
-                    if !tile.is_obstructed {
-                        tile.is_hovered = true
-                    }
-                }),
-                OnPointer::<Out>::target_component_mut::<Tile>(|_, tile| tile.is_hovered = false),
-                OnPointer::<Click>::run_callback(
-                    |In(event): In<ListenedEvent<Click>>,
-                     mut tiles: Query<(Entity, &mut Tile)>,
-                     gi_state: Res<State<GIState>>,
-                     ui_state: Res<State<UIState>>| {
-                        // Clicking should only be allowed during GIState::Unlocked and during UIState::Inventory
-                        if gi_state.0 == GIState::Locked || ui_state.0 == UIState::Inventory {
-                            return Bubble::Up;
-                        }
-
-                        for (entity, mut tile) in &mut tiles {
-                            if entity == event.target && event.button == PointerButton::Primary {
-                                tile.is_clicked = true;
-                            }
-                        }
-
-                        Bubble::Up
-                    },
-                ),
-            ));
+        for group in region.roaming_groups {
+            let elevation = *elevations.get(&group.hex_coord).unwrap_or(&0);
+            spawn_roaming_group(&mut commands, group, &tile_assets, elevation);
         }
+
+        // Tiles are spawned in chunks around the camera over the next frames
+        *region_chunks = RegionChunks::new(region.tiles);
+
         if let Some(enemies) = region.enemies {
             commands.insert_resource(CombatManager::new());
             for enemy in enemies {
-                let mut corrected_enemy = enemy;
-                corrected_enemy.move_timer = Timer::from_seconds(0.5, TimerMode::Repeating);
                 commands.spawn((
                     PbrBundle {
-                        mesh: meshes.add(Mesh::from(shape::Capsule {
-                            radius: 1.2 * SCALE,
-                            rings: 1,
-                            depth: 1.0,
-                            ..default()
-                        })),
-                        material: materials.add(Color::rgb(1.0, 0.5, 0.5).into()),
+                        mesh: tile_assets.enemy_mesh.clone(),
+                        material: tile_assets.enemy_material(enemy.health.hp / enemy.health.max_hp),
                         transform: Transform::from_xyz(
-                            HORIZONTAL_SPACING * corrected_enemy.hex_coord.q as f32
-                                + corrected_enemy.hex_coord.r as f32 % 2.0 * HOR_OFFSET,
-                            2.5,
-                            VERTICAL_SPACING * corrected_enemy.hex_coord.r as f32,
+                            enemy.hex_coord.to_world().x,
+                            2.5 + elevation_at(&enemy.hex_coord),
+                            enemy.hex_coord.to_world().y,
                         ),
                         ..default()
                     },
-                    corrected_enemy,
+                    HexMover::new(enemy.hex_coord, 0.5, 2.5),
+                    StatusEffects::default(),
+                    Combatant::new(Faction::Enemy, Controller::Ai, enemy.movement_range),
+                    enemy,
                 ));
             }
         } else {
@@ -221,16 +329,78 @@ pub fn update_world(
                     .spawn(SceneBundle {
                         scene: asset_server.load("chest.glb#Scene0"),
                         transform: Transform::from_xyz(
-                            chest.hex_coord.q as f32 * HORIZONTAL_SPACING + chest.hex_coord.r as f32 % 2.0 * HOR_OFFSET,
-                            1.0,
+                            chest.hex_coord.to_world().x,
+                            1.0 + elevation_at(&chest.hex_coord),
                             // +2.3 is a rough correction value as the chest glb isn't properly centred at x=0, z=0
-                            chest.hex_coord.r as f32 * VERTICAL_SPACING + 2.3,
+                            chest.hex_coord.to_world().y + 2.3,
                         )
                         .with_scale(Vec3::splat(0.6)),
                         ..default()
                     })
+                    .insert(Interactable::new(InteractionKind::Chest, chest.hex_coord))
                     .insert(chest);
             }
         }
     }
 }
+
+/// Spawns a tile, along with its prop and cliff sides, as a child of a chunk
+pub fn spawn_tile(
+    parent: &mut ChildBuilder,
+    tile: Tile,
+    tile_assets: &TileAssets,
+    prop_palette: &PropPalette,
+    asset_server: &AssetServer,
+) {
+    // Nothing is in view until the fog of war has been calculated for the new tiles
+    let material = tile_assets.tile_material(TileVisual::resting(&tile), true);
+    let visibility = if tile.is_explored {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    let prop = tile.prop.clone();
+    let coord = tile.coord;
+    let elevation = tile.elevation;
+    let mut tile_ent = parent.spawn((
+        PbrBundle {
+            mesh: tile_assets.hex_mesh.clone(),
+            material,
+            transform: Transform::from_scale(Vec3::splat(SCALE))
+                .with_translation(Vec3::new(
+                    tile.coord.to_world().x,
+                    1.0 + elevation_height(tile.elevation),
+                    tile.coord.to_world().y,
+                ))
+                .with_rotation(Quat::from_axis_angle(Vec3 { x: 1.0, y: 0.0, z: 0.0 }, -PI / 2.0)),
+            visibility,
+            ..Default::default()
+        },
+        TileHighlight::new(&tile),
+        tile,
+    ));
+    if let Some(ref prop) = prop {
+        tile_ent.with_children(|parent| spawn_tile_prop(parent, prop, prop_palette, asset_server));
+        if let Some(ref text) = prop.inscription {
+            tile_ent
+                .insert(Interactable::new(InteractionKind::Sign, coord))
+                .insert(Sign { text: text.clone() });
+        }
+    }
+    // Raised tiles get cliff sides reaching down to the base tile height
+    if elevation > 0 {
+        let cliff_height = elevation_height(elevation) / SCALE;
+        tile_ent.with_children(|parent| {
+            parent.spawn(PbrBundle {
+                mesh: tile_assets.cliff_mesh.clone(),
+                material: tile_assets.cliff_material.clone(),
+                // The tile lies flat by being rotated about x, so the cylinder has to be stood back up. The
+                // extra rotation about y lines up the cylinder's corners with the hexagon's
+                transform: Transform::from_xyz(0.0, 0.0, -cliff_height / 2.0 - 0.01)
+                    .with_rotation(Quat::from_rotation_x(PI / 2.0) * Quat::from_rotation_y(PI / 6.0))
+                    .with_scale(Vec3::new(1.0, cliff_height, 1.0)),
+                ..default()
+            });
+        });
+    }
+}
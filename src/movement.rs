@@ -0,0 +1,331 @@
+use crate::*;
+
+/// Plugin responsible for walking things along the hex grid, be it the player, enemies or scripted NPCs.
+///
+/// Nothing moves itself. Anything that wants an entity somewhere sends a [`MoveRequest`], the path is found here and
+/// the entity stepped along it by its [`HexMover`]. [`MoveStarted`], [`StepCompleted`] and [`MoveFinished`] are sent
+/// along the way for other systems to react to, e.g. opening a chest the player has walked into.
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MoveRequest>()
+            .add_event::<MoveStarted>()
+            .add_event::<StepCompleted>()
+            .add_event::<MoveFinished>()
+            .add_system(start_moves.after(update_world))
+            .add_system(advance_moves.after(start_moves));
+    }
+}
+
+/// Asks for an entity with a [`HexMover`] to walk to `target`
+pub struct MoveRequest {
+    pub entity: Entity,
+    pub target: HexCoord,
+    /// The most movement the walk may cost. The path is cut short where this runs out
+    pub max_cost: Option<i32>,
+}
+
+impl MoveRequest {
+    pub fn new(entity: Entity, target: HexCoord) -> MoveRequest {
+        MoveRequest {
+            entity,
+            target,
+            max_cost: None,
+        }
+    }
+
+    pub fn with_max_cost(mut self, max_cost: i32) -> MoveRequest {
+        self.max_cost = Some(max_cost);
+        self
+    }
+}
+
+/// An entity has set off. Not sent when there was nowhere to go, in which case only [`MoveFinished`] is
+pub struct MoveStarted {
+    pub entity: Entity,
+    /// Tiles that will be walked to, not including the one the entity started on
+    pub path: Vec<HexCoord>,
+    /// Movement spent on the path
+    pub cost: i32,
+}
+
+/// An entity has arrived on the next tile of its path
+pub struct StepCompleted {
+    pub entity: Entity,
+    pub from: HexCoord,
+    pub to: HexCoord,
+}
+
+/// An entity has stopped, either at the end of its path or because it was stopped early. Sent once for every
+/// [`MoveRequest`]
+pub struct MoveFinished {
+    pub entity: Entity,
+    pub coord: HexCoord,
+}
+
+/// How the progress of a step is spread over its duration
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Easing {
+    /// Constant speed, so consecutive steps blend into a continuous walk
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    /// Speeds up and slows down again on every tile
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps the fraction of the step's duration that has passed to how far along the step the entity is
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// Fraction of a step spent turning to face the tile being walked to
+const TURN_FRACTION: f32 = 0.3;
+
+/// A step to an adjacent tile that's under way
+struct Step {
+    to: HexCoord,
+    start_translation: Vec3,
+    end_translation: Vec3,
+    start_rotation: Quat,
+    end_rotation: Quat,
+    timer: Timer,
+}
+
+#[derive(Component)]
+pub struct HexMover {
+    /// Where the entity stands. Players and enemies keep their own `hex_coord` up to date instead, as the rest of the
+    /// game reads that
+    pub coord: HexCoord,
+    /// Height the entity's transform sits above the surface of the tile it's standing on
+    pub height: f32,
+    /// Animation of the entity's [`RiggedEntity`] played for every step, if it has one
+    pub run_animation: Option<usize>,
+    /// Seconds a step takes at normal speed
+    pub step_duration: f32,
+    /// Multiplies how fast steps, and the running animation, play
+    pub speed: f32,
+    pub easing: Easing,
+    /// Tiles still to be walked to, not including the one being walked to now
+    path: Vec<HexCoord>,
+    step: Option<Step>,
+    moving: bool,
+}
+
+impl HexMover {
+    /// `step_duration`: How long walking to an adjacent tile takes. For rigged entities this should be the duration
+    /// of their running animation, so a step lines up with one loop of it
+    /// ```
+    /// fn my_system(re_map: Res<REntityMap>) {
+    ///     let run_dur = re_map.0.get(&REntityType::Kraug).unwrap().animations[9].duration;
+    /// }
+    /// ```
+    pub fn new(coord: HexCoord, step_duration: f32, height: f32) -> HexMover {
+        HexMover {
+            coord,
+            height,
+            run_animation: None,
+            step_duration,
+            speed: 1.0,
+            easing: Easing::default(),
+            path: vec![],
+            step: None,
+            moving: false,
+        }
+    }
+
+    pub fn with_run_animation(mut self, animation: usize) -> HexMover {
+        self.run_animation = Some(animation);
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> HexMover {
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> HexMover {
+        self.easing = easing;
+        self
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.moving
+    }
+
+    /// Stops the entity once it reaches the tile it's walking to. [`MoveFinished`] is still sent
+    pub fn stop(&mut self) {
+        self.path.clear();
+    }
+
+    /// Stops the entity straight away, even between two tiles. For when it's about to be placed somewhere else, e.g.
+    /// by a region loading. [`MoveFinished`] is still sent
+    pub fn halt(&mut self) {
+        self.path.clear();
+        self.step = None;
+    }
+}
+
+/// Where a mover is standing. See [`HexMover::coord`]
+fn mover_coord(mover: &HexMover, player: Option<&Player>, enemy: Option<&Enemy>) -> HexCoord {
+    player
+        .map(|p| p.hex_coord)
+        .or(enemy.map(|e| e.hex_coord))
+        .unwrap_or(mover.coord)
+}
+
+/// Finds a path for every [`MoveRequest`] and sets its entity off along it
+pub fn start_moves(
+    mut move_requests: EventReader<MoveRequest>,
+    mut movers: Query<(Entity, &mut HexMover, Option<&Player>, Option<&Enemy>)>,
+    region_chunks: Res<RegionChunks>,
+    mut move_started_sender: EventWriter<MoveStarted>,
+    mut move_finished_sender: EventWriter<MoveFinished>,
+) {
+    for request in move_requests.iter() {
+        // Movers can't walk through each other
+        let occupied: Vec<HexCoord> = movers
+            .iter()
+            .filter(|(entity, ..)| *entity != request.entity)
+            .map(|(_, mover, player, enemy)| mover_coord(mover, player, enemy))
+            .collect();
+
+        let Ok((_, mut mover, player, enemy)) = movers.get_mut(request.entity) else {
+            warn!("Movement was requested for {:?}, which can't move", request.entity);
+            continue;
+        };
+        if mover.moving {
+            debug!("{:?} is already moving, ignoring its move request", request.entity);
+            continue;
+        }
+        let start = mover_coord(&mover, player, enemy);
+
+        // Paths can lead through chunks that aren't spawned, so the terrain comes from the whole region
+        let mut obstructed_tiles = region_chunks.obstructed_tiles();
+        let elevations = region_chunks.elevations();
+        let path = if obstructed_tiles.contains(&request.target) || occupied.contains(&request.target) {
+            None
+        } else {
+            obstructed_tiles.extend(occupied);
+            astar(start, request.target, &obstructed_tiles, elevations)
+        };
+
+        mover.moving = true;
+
+        // Unreachable, e.g. it's on top of a cliff. The mover finishes straight away
+        let Some(mut path) = path else {
+            continue;
+        };
+        path.remove(0);
+        let max_cost = request.max_cost.unwrap_or(i32::MAX);
+        let cost = truncate_path_to_budget(&mut path, start, max_cost, elevations);
+        if path.is_empty() {
+            continue;
+        }
+
+        trace!("path len {}", path.len());
+        mover.path = path.clone();
+        move_started_sender.send(MoveStarted {
+            entity: request.entity,
+            path,
+            cost,
+        });
+    }
+
+    // Sent here rather than above so a mover with nowhere to go still finishes after its request has been handled
+    for (entity, mut mover, player, enemy) in &mut movers {
+        if mover.moving && mover.path.is_empty() && mover.step.is_none() {
+            mover.moving = false;
+            move_finished_sender.send(MoveFinished {
+                entity,
+                coord: mover_coord(&mover, player, enemy),
+            });
+        }
+    }
+}
+
+/// Walks moving entities along their paths.
+///
+/// Each step interpolates the transform from one tile to the next, turning to face the way it's going at the start
+/// of the step. The next step starts the frame after one is completed, so anything reacting to [`StepCompleted`] can
+/// stop the entity where it is
+#[allow(clippy::type_complexity)]
+pub fn advance_moves(
+    mut movers: Query<(
+        Entity,
+        &mut HexMover,
+        &mut Transform,
+        Option<&mut Player>,
+        Option<&mut Enemy>,
+        Option<&mut RiggedEntity>,
+    )>,
+    region_chunks: Res<RegionChunks>,
+    time: Res<Time>,
+    mut step_completed_sender: EventWriter<StepCompleted>,
+) {
+    let elevations = region_chunks.elevations();
+    let surface_height =
+        |mover: &HexMover, coord: &HexCoord| mover.height + elevation_height(*elevations.get(coord).unwrap_or(&0));
+
+    for (entity, mut mover, mut transform, mut player, mut enemy, rigged_entity) in &mut movers {
+        if !mover.moving {
+            continue;
+        }
+        let from = mover_coord(&mover, player.as_deref(), enemy.as_deref());
+
+        // The mover is finished with once its path runs out, see start_moves
+        if mover.step.is_none() && !mover.path.is_empty() {
+            let to = mover.path.remove(0);
+            let end_position = to.to_world();
+            let step = Step {
+                to,
+                start_translation: transform.translation,
+                end_translation: Vec3::new(end_position.x, surface_height(&mover, &to), end_position.y),
+                start_rotation: transform.rotation,
+                end_rotation: Quat::from_rotation_y(rotation_to(from, to)),
+                timer: Timer::from_seconds(mover.step_duration / mover.speed, TimerMode::Once),
+            };
+            mover.step = Some(step);
+            if let (Some(animation), Some(mut rigged_entity)) = (mover.run_animation, rigged_entity) {
+                rigged_entity.pend_with_speed(animation, mover.speed);
+            }
+            continue;
+        }
+
+        let easing = mover.easing;
+        let Some(step) = &mut mover.step else {
+            continue;
+        };
+        step.timer.tick(time.delta());
+        let progress = step.timer.percent();
+        transform.translation = step
+            .start_translation
+            .lerp(step.end_translation, easing.apply(progress));
+        transform.rotation = step
+            .start_rotation
+            .slerp(step.end_rotation, Easing::EaseOut.apply(progress / TURN_FRACTION));
+
+        if step.timer.finished() {
+            let to = step.to;
+            mover.step = None;
+            mover.coord = to;
+            if let Some(player) = &mut player {
+                player.hex_coord = to;
+            }
+            if let Some(enemy) = &mut enemy {
+                enemy.hex_coord = to;
+            }
+            step_completed_sender.send(StepCompleted { entity, from, to });
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use bevy::window::PrimaryWindow;
+
+use crate::*;
+
+/// Plugin responsible for working out which tile is under the cursor.
+///
+/// Rather than raycasting against every tile mesh, the cursor ray is intersected with the plane of each elevation
+/// and the hit point converted straight to a [`HexCoord`]. The picked tile gets its hover state and highlight layer
+/// set, and a [`TileClicked`] is sent when it's clicked.
+pub struct HexPickingPlugin;
+
+impl Plugin for HexPickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TileClicked>()
+            .add_system(pick_tiles.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// The player has clicked on an explored tile
+pub struct TileClicked {
+    pub coord: HexCoord,
+}
+
+/// Where a ray crosses the horizontal plane at height `y`, if it does so in front of the ray's origin
+pub fn ray_plane_intersection(ray: Ray, y: f32) -> Option<Vec3> {
+    if ray.direction.y.abs() < f32::EPSILON {
+        return None;
+    }
+    let distance = (y - ray.origin.y) / ray.direction.y;
+    if distance < 0.0 {
+        return None;
+    }
+    Some(ray.get_point(distance))
+}
+
+/// The tile hit by a ray, checking the highest elevations first so raised tiles hide the ones behind them
+fn tile_under_ray(ray: Ray, region_chunks: &RegionChunks) -> Option<HexCoord> {
+    for elevation in (0..=region_chunks.max_elevation()).rev() {
+        let Some(point) = ray_plane_intersection(ray, 1.0 + elevation_height(elevation)) else {
+            continue;
+        };
+        let coord = HexCoord::world_to_hex(point.x, point.z);
+        if region_chunks.elevations().get(&coord) == Some(&elevation) {
+            return Some(coord);
+        }
+    }
+    None
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn pick_tiles(
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<PlayerCameraMarker>>,
+    mouse: Res<Input<MouseButton>>,
+    mut tiles: Query<(&mut Tile, &mut TileHighlight)>,
+    mut tile_clicked_sender: EventWriter<TileClicked>,
+    region_chunks: Res<RegionChunks>,
+    gi_state: Res<State<GIState>>,
+    ui_state: Res<State<UIState>>,
+    ui_interactions: Query<&Interaction>,
+) {
+    // Tiles can only be interacted with when nothing else is going on, and not through UI buttons
+    let can_pick = gi_state.0 == GIState::Unlocked
+        && ui_state.0 != UIState::Inventory
+        && ui_state.0 != UIState::WorldMap
+        && ui_state.0 != UIState::Character
+        && ui_interactions
+            .iter()
+            .all(|interaction| *interaction == Interaction::None);
+
+    let picked = match (primary_window.get_single(), camera.get_single()) {
+        (Ok(window), Ok((camera, camera_transform))) if can_pick => window
+            .cursor_position()
+            .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+            .and_then(|ray| tile_under_ray(ray, &region_chunks)),
+        _ => None,
+    };
+
+    for (mut tile, mut highlight) in &mut tiles {
+        let is_picked = picked == Some(tile.coord) && tile.is_explored;
+
+        // Only touch tiles whose state actually changes, so change detection stays meaningful
+        let hovered = is_picked && !tile.is_obstructed;
+        if tile.is_hovered != hovered {
+            tile.is_hovered = hovered;
+        }
+        set_highlight(&mut highlight, HighlightLayer::Hover, hovered);
+        // Unexplored tiles are hidden by the fog of war and can't be travelled to directly
+        if is_picked && mouse.just_pressed(MouseButton::Left) {
+            tile_clicked_sender.send(TileClicked { coord: tile.coord });
+        }
+    }
+}
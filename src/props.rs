@@ -0,0 +1,133 @@
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use crate::*;
+
+/// Plugin responsible for loading the prop palette from assets/props.json.
+///
+/// Tiles reference props by name (see [`TileProp`]) and [`update_world`] spawns them as child scenes of the tile.
+/// Props whose models are missing from the assets folder are left out of the palette, with a warning.
+pub struct PropPlugin;
+
+impl Plugin for PropPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PropPalette>();
+    }
+}
+
+/// A decoration placed on a tile, such as a tree, rock, pillar or crate
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub struct TileProp {
+    /// Name of the prop in the palette
+    pub kind: String,
+    /// Rotation around the y axis, in radians
+    #[serde(default)]
+    pub rotation: f32,
+    /// Multiplier on top of the palette's scale for this prop
+    #[serde(default = "default_prop_scale")]
+    pub scale: f32,
+    /// Text carved into the prop, which makes it a [`Sign`] the player can read
+    #[serde(default)]
+    pub inscription: Option<String>,
+}
+
+fn default_prop_scale() -> f32 {
+    1.0
+}
+
+/// An entry of the asset manifest
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PropAsset {
+    /// Path of the scene relative to the assets folder, e.g. `props/tree.glb#Scene0`
+    pub scene: String,
+    /// World space scale the model needs to fit on a single tile
+    pub scale: f32,
+}
+
+#[derive(Resource)]
+/// Every prop that can be placed on a tile, keyed by name. Loaded from assets/props.json
+pub struct PropPalette(pub HashMap<String, PropAsset>);
+
+impl Default for PropPalette {
+    fn default() -> Self {
+        let manifest = fs::read_to_string("assets/props.json").expect("Something went wrong reading the file");
+        let mut palette: HashMap<String, PropAsset> = serde_json::from_str(&manifest).unwrap();
+
+        // A missing model shouldn't stop the game, so skip it. every_prop_has_a_model catches these
+        palette.retain(|name, prop| {
+            let exists = prop.model_exists();
+            if !exists {
+                warn!(
+                    "Model {} for prop '{}' is missing, it won't be spawned",
+                    prop.model_path(),
+                    name
+                );
+            }
+            exists
+        });
+
+        PropPalette(palette)
+    }
+}
+
+impl PropAsset {
+    /// Path of the model file relative to the assets folder, without the label of the scene in it
+    pub fn model_path(&self) -> &str {
+        self.scene.split('#').next().unwrap_or_default()
+    }
+
+    pub fn model_exists(&self) -> bool {
+        Path::new("assets").join(self.model_path()).exists()
+    }
+}
+
+/// Spawns a prop as a child of its tile entity.
+///
+/// Tiles are scaled by [`SCALE`] and rotated to lie flat, so the prop's transform undoes that to stand upright at
+/// the intended world space size.
+pub fn spawn_tile_prop(parent: &mut ChildBuilder, prop: &TileProp, palette: &PropPalette, asset_server: &AssetServer) {
+    let Some(prop_asset) = palette.0.get(&prop.kind) else {
+        warn!("There is no prop called '{}' in assets/props.json", prop.kind);
+        return;
+    };
+
+    parent.spawn(SceneBundle {
+        scene: asset_server.load(prop_asset.scene.as_str()),
+        transform: Transform::from_scale(Vec3::splat(prop_asset.scale * prop.scale / SCALE))
+            .with_rotation(Quat::from_rotation_x(PI / 2.0) * Quat::from_rotation_y(prop.rotation)),
+        ..default()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_prop_has_a_model() {
+        let manifest = fs::read_to_string("assets/props.json").unwrap();
+        let palette: HashMap<String, PropAsset> = serde_json::from_str(&manifest).unwrap();
+        for (name, prop) in &palette {
+            assert!(
+                prop.model_exists(),
+                "Model {} for prop '{}' is missing",
+                prop.model_path(),
+                name
+            );
+        }
+
+        let world = fs::read_to_string("default_world.json").unwrap();
+        let world: HashMap<String, Region> = serde_json::from_str(&world).unwrap();
+        for (id, region) in &world {
+            for prop in region.tiles.iter().filter_map(|tile| tile.prop.as_ref()) {
+                assert!(
+                    palette.contains_key(&prop.kind),
+                    "Region {} has a '{}' prop, which isn't in assets/props.json",
+                    id,
+                    prop.kind
+                );
+            }
+        }
+    }
+}
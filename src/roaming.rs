@@ -0,0 +1,193 @@
+use rand::Rng;
+
+use crate::*;
+
+/// Plugin responsible for groups of enemies roaming non-combat regions.
+///
+/// [`update_world`] spawns the [`RoamingGroup`]s of every region it loads. They patrol or wander until the player
+/// comes within their aggro radius, then chase them. Once a group reaches the player the fight starts, either in the
+/// group's combat subregion or in an arena built on the spot (see [`MapContext::start_arena`]), and the group is
+/// removed from the region for good.
+pub struct RoamingPlugin;
+
+impl Plugin for RoamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems((roam, engage_roaming_groups.after(roam)).in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+/// How long a group waits between walks when it isn't chasing the player
+const ROAMING_PAUSE: f32 = 1.5;
+
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub enum RoamBehaviour {
+    /// Walks between the tiles of a route, in a loop
+    Patrol { route: Vec<HexCoord> },
+    /// Walks to random tiles within `radius` of where it started
+    Wander { radius: i32 },
+}
+
+/// A group of enemies walking around a region, as stored in world.json
+#[derive(Serialize, Deserialize, Reflect, FromReflect, Clone, Debug)]
+pub struct RoamingGroup {
+    /// Tells the groups of a region apart, so the right one is removed once it has been fought
+    pub id: String,
+    pub hex_coord: HexCoord,
+    pub behaviour: RoamBehaviour,
+    /// How close the player has to get for the group to start chasing them
+    pub aggro_radius: i32,
+    /// The enemies fought when the group reaches the player. Their coords are where they stand in `combat_region`,
+    /// and are ignored when the fight happens on the spot
+    pub enemies: Vec<Enemy>,
+    /// The combat subregion the fight takes place in. Without one, it happens on the spot
+    #[serde(default)]
+    pub combat_region: Option<String>,
+}
+
+#[derive(Component)]
+pub struct RoamingEnemyGroup {
+    pub group: RoamingGroup,
+    /// Index of the route tile the group is heading to when patrolling
+    next_waypoint: usize,
+    /// Counts down the pause between walks
+    pause: Timer,
+}
+
+/// Spawns a roaming group of the region being loaded, see [`update_world`]
+pub fn spawn_roaming_group(commands: &mut Commands, group: RoamingGroup, tile_assets: &TileAssets, elevation: i32) {
+    let position = group.hex_coord.to_world();
+    commands.spawn((
+        PbrBundle {
+            mesh: tile_assets.enemy_mesh.clone(),
+            material: tile_assets.enemy_material(1.0),
+            // A bit bigger than the enemies in combat, as it stands for a whole group
+            transform: Transform::from_xyz(position.x, 2.5 + elevation_height(elevation), position.y)
+                .with_scale(Vec3::splat(1.3)),
+            ..default()
+        },
+        Name::new(format!("Roaming group {}", group.id)),
+        HexMover::new(group.hex_coord, 0.6, 2.5),
+        RoamingEnemyGroup {
+            group,
+            next_waypoint: 0,
+            pause: Timer::from_seconds(ROAMING_PAUSE, TimerMode::Once),
+        },
+    ));
+}
+
+/// The free tile next to `target` closest to `from`
+fn tile_next_to(target: &HexCoord, from: &HexCoord, region_chunks: &RegionChunks) -> Option<HexCoord> {
+    let obstructed_tiles = region_chunks.obstructed_tiles();
+    get_neighbors(target)
+        .into_iter()
+        .filter(|coord| region_chunks.elevations().contains_key(coord) && !obstructed_tiles.contains(coord))
+        .min_by_key(|coord| hex_distance(coord, from))
+}
+
+/// Walks roaming groups along their patrol routes, or around where they started, and after the player when they get
+/// too close
+#[allow(clippy::too_many_arguments)]
+pub fn roam(
+    mut groups: Query<(Entity, &mut RoamingEnemyGroup, &HexMover)>,
+    player: Query<&Player>,
+    region_chunks: Res<RegionChunks>,
+    opt_combat_manager: Option<Res<CombatManager>>,
+    ui_state: Res<State<UIState>>,
+    time: Res<Time>,
+    mut move_request_sender: EventWriter<MoveRequest>,
+) {
+    // Nothing roams around during combat, or while the player is busy in a menu
+    if opt_combat_manager.is_some() || ui_state.0 != UIState::Null {
+        return;
+    }
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+
+    for (entity, mut roaming, mover) in &mut groups {
+        if mover.is_moving() {
+            continue;
+        }
+        let coord = mover.coord;
+        // Borrowed through once, so the route can be read while the next waypoint is updated
+        let roaming = &mut *roaming;
+
+        // Chase the player a step at a time, so the group keeps up with where they've gone
+        if hex_distance(&coord, &player.hex_coord) <= roaming.group.aggro_radius {
+            if let Some(target) = tile_next_to(&player.hex_coord, &coord, &region_chunks) {
+                if target != coord {
+                    move_request_sender.send(MoveRequest::new(entity, target).with_max_cost(1));
+                }
+            }
+            continue;
+        }
+
+        roaming.pause.tick(time.delta());
+        if !roaming.pause.finished() {
+            continue;
+        }
+        roaming.pause.reset();
+
+        let target = match roaming.group.behaviour {
+            RoamBehaviour::Patrol { ref route } => {
+                if route.is_empty() {
+                    continue;
+                }
+                if route[roaming.next_waypoint % route.len()] == coord {
+                    roaming.next_waypoint = (roaming.next_waypoint + 1) % route.len();
+                }
+                route[roaming.next_waypoint % route.len()]
+            }
+            RoamBehaviour::Wander { radius } => {
+                let home = roaming.group.hex_coord;
+                let mut rng = rand::thread_rng();
+                HexCoord::new(
+                    rng.gen_range(home.q - radius..=home.q + radius),
+                    rng.gen_range(home.r - radius..=home.r + radius),
+                )
+            }
+        };
+        // Wandering can pick tiles outside the region, or too far from home. The group just waits for the next pick
+        let in_region = region_chunks.elevations().contains_key(&target);
+        let in_reach = match roaming.group.behaviour {
+            RoamBehaviour::Wander { radius } => hex_distance(&target, &roaming.group.hex_coord) <= radius,
+            RoamBehaviour::Patrol { .. } => true,
+        };
+        if target != coord && in_region && in_reach {
+            move_request_sender.send(MoveRequest::new(entity, target));
+        }
+    }
+}
+
+/// Starts a fight when a roaming group reaches the player
+pub fn engage_roaming_groups(
+    mut groups: Query<(&RoamingEnemyGroup, &mut HexMover), Without<Player>>,
+    mut player: Query<(&Player, &mut HexMover)>,
+    mut map_context: ResMut<MapContext>,
+    opt_combat_manager: Option<Res<CombatManager>>,
+) {
+    // The groups are despawned once the fight's region has loaded
+    if opt_combat_manager.is_some() || map_context.load_new_region {
+        return;
+    }
+    let Ok((player, mut player_mover)) = player.get_single_mut() else {
+        return;
+    };
+
+    for (roaming, mut group_mover) in &mut groups {
+        if hex_distance(&group_mover.coord, &player.hex_coord) > 1 {
+            continue;
+        }
+        let group = &roaming.group;
+        info!("Roaming group {} has caught up with the player", group.id);
+
+        player_mover.halt();
+        group_mover.halt();
+        map_context.remove_roaming_group(&group.id);
+        match group.combat_region {
+            Some(ref combat_region) => map_context.start_subregion_fight(combat_region, group.enemies.clone()),
+            None => map_context.start_arena(group_mover.coord, player.hex_coord, group.enemies.clone()),
+        }
+        return;
+    }
+}
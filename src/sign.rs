@@ -0,0 +1,97 @@
+use bevy::window::PrimaryWindow;
+
+use crate::*;
+
+/// Plugin responsible for signs, which are props with an inscription (see [`TileProp`]).
+///
+/// Reading a sign shows its text at the bottom of the screen until the player walks off the tile they read it from.
+pub struct SignPlugin;
+
+impl Plugin for SignPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SignReadEvent>()
+            .add_system(on_sign_read.after(dispatch_interactions))
+            .add_system(close_sign_panel.in_set(OnUpdate(GameState::InGame)));
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Sign {
+    pub text: String,
+}
+
+pub struct SignReadEvent {
+    pub sign_ent: Entity,
+    pub text: String,
+}
+
+#[derive(Component)]
+struct SignPanel {
+    /// Where the player was standing when they read the sign
+    read_from: HexCoord,
+}
+
+fn on_sign_read(
+    mut commands: Commands,
+    mut sign_read_events: EventReader<SignReadEvent>,
+    open_panels: Query<Entity, With<SignPanel>>,
+    player: Query<&Player>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    asset_server: Res<AssetServer>,
+) {
+    let Some(event) = sign_read_events.iter().last() else {
+        return;
+    };
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+
+    for panel in &open_panels {
+        commands.entity(panel).despawn_recursive();
+    }
+
+    let width = primary_window.single().resolution.width() / 2.0;
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(width / 2.0),
+                    bottom: Val::Px(width / 20.0),
+                    ..default()
+                },
+                size: Size::width(Val::Px(width)),
+                padding: UiRect::all(Val::Px(width / 40.0)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            background_color: Color::rgba(0.13, 0.14, 0.26, 0.9).into(),
+            ..default()
+        })
+        .insert(Name::new("Sign panel"))
+        .insert(SignPanel {
+            read_from: player.hex_coord,
+        })
+        .with_children(|commands| {
+            commands.spawn(TextBundle::from_section(
+                event.text.clone(),
+                TextStyle {
+                    font: asset_server.load("font.otf"),
+                    font_size: width / 30.0,
+                    color: Color::WHITE,
+                },
+            ));
+        });
+}
+
+fn close_sign_panel(mut commands: Commands, panels: Query<(Entity, &SignPanel)>, player: Query<&Player>) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    for (entity, panel) in &panels {
+        if panel.read_from != player.hex_coord {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
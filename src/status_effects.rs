@@ -0,0 +1,237 @@
+use crate::*;
+
+/// Plugin responsible for status effects lasting a number of turns in combat, such as burning or being stunned.
+///
+/// The player and every enemy have [`StatusEffects`], which abilities add to (see [`Ability::effects`]). A unit's
+/// effects tick at the end of each of its turns, see [`CombatState`]. Effects are cleared once combat is over.
+pub struct StatusEffectPlugin;
+
+impl Plugin for StatusEffectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(clear_status_effects.run_if(resource_removed::<CombatManager>()));
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StatusKind {
+    /// Takes `potency` damage at the end of every turn. Reapplying refreshes it
+    Burn,
+    /// Takes `potency` damage at the end of every turn. Reapplying makes it stronger
+    Poison,
+    /// Skips its turns
+    Stun,
+    /// Can move `potency` fewer tiles every turn
+    Slow,
+    /// Absorbs up to `potency` damage from attacks and abilities. Reapplying makes it stronger
+    Shield,
+    /// Heals `potency` at the end of every turn. Reapplying makes it last longer
+    Regen,
+    /// Deals `potency` less of its damage with attacks and abilities, e.g. a quarter less for 0.25
+    Weaken,
+}
+
+/// What happens when an effect is applied to a unit that already has one of its kind
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stacking {
+    /// Keeps the stronger of the two, lasting as long as the longer one
+    Refresh,
+    /// Adds up their potencies, lasting as long as the longer one
+    Intensify,
+    /// Adds up their durations, keeping the stronger one
+    Extend,
+}
+
+impl StatusKind {
+    pub fn stacking(&self) -> Stacking {
+        match self {
+            StatusKind::Burn | StatusKind::Stun | StatusKind::Slow | StatusKind::Weaken => Stacking::Refresh,
+            StatusKind::Poison | StatusKind::Shield => Stacking::Intensify,
+            StatusKind::Regen => Stacking::Extend,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// Damage, healing, tiles of movement, shield points or the fraction of damage lost, depending on the kind.
+    /// Unused by [`StatusKind::Stun`]
+    #[serde(default)]
+    pub potency: f32,
+    /// How many more of its unit's turns the effect lasts
+    pub turns: u32,
+}
+
+/// The effects a unit in combat is under, at most one of each kind
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+impl StatusEffects {
+    pub fn get(&self, kind: StatusKind) -> Option<&StatusEffect> {
+        self.0.iter().find(|effect| effect.kind == kind)
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.get(kind).is_some()
+    }
+
+    /// How strong the unit's effect of the given kind is, 0 if it doesn't have one
+    pub fn potency(&self, kind: StatusKind) -> f32 {
+        self.get(kind).map_or(0.0, |effect| effect.potency)
+    }
+
+    /// Adds an effect, combining it with any of the same kind according to its [`Stacking`]
+    pub fn apply(&mut self, effect: StatusEffect) {
+        let Some(existing) = self.0.iter_mut().find(|existing| existing.kind == effect.kind) else {
+            self.0.push(effect);
+            return;
+        };
+        match effect.kind.stacking() {
+            Stacking::Refresh => {
+                existing.potency = existing.potency.max(effect.potency);
+                existing.turns = existing.turns.max(effect.turns);
+            }
+            Stacking::Intensify => {
+                existing.potency += effect.potency;
+                existing.turns = existing.turns.max(effect.turns);
+            }
+            Stacking::Extend => {
+                existing.potency = existing.potency.max(effect.potency);
+                existing.turns += effect.turns;
+            }
+        }
+    }
+
+    /// Whether the unit can take its turn
+    pub fn can_act(&self) -> bool {
+        !self.has(StatusKind::Stun)
+    }
+
+    /// How many tiles the unit can move in a turn, given how many it normally could
+    pub fn movement_range(&self, base: i32) -> i32 {
+        (base - self.potency(StatusKind::Slow).round() as i32).max(0)
+    }
+
+    /// Multiplies the damage the unit deals
+    pub fn damage_multiplier(&self) -> f32 {
+        (1.0 - self.potency(StatusKind::Weaken)).max(0.0)
+    }
+
+    /// Lets the unit's shield soak up what it can of `damage`, returning the damage that gets through
+    pub fn absorb(&mut self, damage: f32) -> f32 {
+        let Some(shield) = self.0.iter_mut().find(|effect| effect.kind == StatusKind::Shield) else {
+            return damage;
+        };
+        let absorbed = shield.potency.min(damage.max(0.0));
+        shield.potency -= absorbed;
+        if shield.potency <= 0.0 {
+            self.0.retain(|effect| effect.kind != StatusKind::Shield);
+        }
+        damage - absorbed
+    }
+
+    /// Ticks every effect at the end of the unit's turn, burning, poisoning and regenerating it and removing the
+    /// effects that have run out. Burn and poison go straight through shields
+    pub fn end_turn(&mut self, health: &mut Health) {
+        health.hp -= self.potency(StatusKind::Burn) + self.potency(StatusKind::Poison);
+        health.hp = (health.hp + self.potency(StatusKind::Regen)).min(health.max_hp);
+        for effect in &mut self.0 {
+            effect.turns = effect.turns.saturating_sub(1);
+        }
+        self.0.retain(|effect| effect.turns > 0);
+    }
+
+    /// Describes the effects for the combat UI, e.g. "Burn 3 (2 turns), Stun (1 turn)"
+    pub fn describe(&self) -> String {
+        if self.0.is_empty() {
+            return "None".to_string();
+        }
+        self.0
+            .iter()
+            .map(|effect| {
+                let turns = if effect.turns == 1 { "turn" } else { "turns" };
+                match effect.kind {
+                    StatusKind::Stun => format!("{:?} ({} {})", effect.kind, effect.turns, turns),
+                    _ => format!("{:?} {} ({} {})", effect.kind, effect.potency, effect.turns, turns),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+pub fn clear_status_effects(mut units: Query<&mut StatusEffects>) {
+    for mut effects in &mut units {
+        effects.0.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn effect(kind: StatusKind, potency: f32, turns: u32) -> StatusEffect {
+        StatusEffect { kind, potency, turns }
+    }
+
+    #[test]
+    fn refreshing_keeps_the_stronger_and_longer() {
+        let mut effects = StatusEffects::default();
+        effects.apply(effect(StatusKind::Burn, 3.0, 1));
+        effects.apply(effect(StatusKind::Burn, 2.0, 3));
+        assert_eq!(effects.0, vec![effect(StatusKind::Burn, 3.0, 3)]);
+    }
+
+    #[test]
+    fn intensifying_adds_up_potency() {
+        let mut effects = StatusEffects::default();
+        effects.apply(effect(StatusKind::Poison, 2.0, 3));
+        effects.apply(effect(StatusKind::Poison, 2.0, 1));
+        assert_eq!(effects.0, vec![effect(StatusKind::Poison, 4.0, 3)]);
+    }
+
+    #[test]
+    fn extending_adds_up_turns() {
+        let mut effects = StatusEffects::default();
+        effects.apply(effect(StatusKind::Regen, 3.0, 2));
+        effects.apply(effect(StatusKind::Regen, 1.0, 2));
+        assert_eq!(effects.0, vec![effect(StatusKind::Regen, 3.0, 4)]);
+    }
+
+    #[test]
+    fn damage_past_the_shield_gets_through() {
+        let mut effects = StatusEffects(vec![effect(StatusKind::Shield, 10.0, 2)]);
+        assert_eq!(effects.absorb(4.0), 0.0);
+        assert_eq!(effects.potency(StatusKind::Shield), 6.0);
+        assert_eq!(effects.absorb(10.0), 4.0);
+        assert!(!effects.has(StatusKind::Shield));
+    }
+
+    #[test]
+    fn burn_and_poison_ignore_shields() {
+        let mut effects = StatusEffects(vec![
+            effect(StatusKind::Shield, 10.0, 2),
+            effect(StatusKind::Burn, 3.0, 1),
+            effect(StatusKind::Poison, 2.0, 2),
+        ]);
+        let mut health = Health::new(50.0);
+        effects.end_turn(&mut health);
+        assert_eq!(health.hp, 45.0);
+        assert_eq!(effects.potency(StatusKind::Shield), 10.0);
+        // The burn has run out
+        assert_eq!(
+            effects.0,
+            vec![effect(StatusKind::Shield, 10.0, 1), effect(StatusKind::Poison, 2.0, 1)]
+        );
+    }
+
+    #[test]
+    fn weakened_units_deal_less_damage() {
+        let mut effects = StatusEffects::default();
+        assert_eq!(effects.damage_multiplier(), 1.0);
+        effects.apply(effect(StatusKind::Weaken, 0.25, 1));
+        assert_eq!(effects.damage_multiplier(), 0.75);
+        effects.apply(effect(StatusKind::Weaken, 2.0, 1));
+        assert_eq!(effects.damage_multiplier(), 0.0);
+    }
+}
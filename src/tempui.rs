@@ -169,9 +169,10 @@ pub enum NonCombatButtonType {
 #[derive(Clone, Copy, PartialEq, FromReflect, Reflect, Debug)]
 pub enum AcitonType {
     EndPhase,
-    Fireball,
-    Smack,
-    RunSmack,
+    /// Takes back the player's last walk of the movement phase
+    UndoMove,
+    /// Index of the ability in [`CombatUnit::abilities`], i.e. among the player's abilities in the [`AbilityBook`]
+    Ability(usize),
 }
 
 #[derive(Component)]
@@ -0,0 +1,163 @@
+use crate::*;
+
+/// Plugin responsible for the meshes and materials shared between every tile and enemy.
+///
+/// Tiles don't own their materials. Changing how a tile looks means swapping its handle for another one from
+/// [`TileAssets`] (see [`apply_tile_highlights`]), so loading a region never adds assets and highlighting never
+/// mutates them.
+pub struct TileAssetsPlugin;
+
+impl Plugin for TileAssetsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileAssets>();
+    }
+}
+
+/// The different ways a tile can be drawn
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum TileVisual {
+    Base,
+    UnclearedCombat,
+    ClearedCombat,
+    /// Entrance to a non-combat subregion
+    Subregion,
+    Waypoint,
+    Obstructed,
+    Hovered,
+    /// Part of the path the player is walking along
+    Path,
+    /// Can be targeted by the selected combat action
+    Target,
+    /// Would be hit by the selected combat action
+    Splash,
+    /// Occupied by an enemy
+    Danger,
+    /// Occupied by someone who would be hit by the selected combat action
+    SplashHit,
+    /// Where the player is heading
+    Selection,
+}
+
+impl TileVisual {
+    const ALL: [TileVisual; 13] = [
+        TileVisual::Base,
+        TileVisual::UnclearedCombat,
+        TileVisual::ClearedCombat,
+        TileVisual::Subregion,
+        TileVisual::Waypoint,
+        TileVisual::Obstructed,
+        TileVisual::Hovered,
+        TileVisual::Path,
+        TileVisual::Target,
+        TileVisual::Splash,
+        TileVisual::Danger,
+        TileVisual::SplashHit,
+        TileVisual::Selection,
+    ];
+
+    /// How a tile looks when nothing is happening to it
+    pub fn resting(tile: &Tile) -> TileVisual {
+        if tile.is_obstructed {
+            return TileVisual::Obstructed;
+        }
+        if tile.is_waypoint {
+            return TileVisual::Waypoint;
+        }
+        match tile.sub_region_id {
+            Some(ref sub_region_data) => match sub_region_data.subregion_type {
+                SubregionType::UnclearedCombat => TileVisual::UnclearedCombat,
+                SubregionType::ClearedCombat => TileVisual::ClearedCombat,
+                SubregionType::Other => TileVisual::Subregion,
+            },
+            None => TileVisual::Base,
+        }
+    }
+
+    pub fn colour(&self) -> Color {
+        match self {
+            TileVisual::Base => Color::rgba(1.0, 1.0, 1.0, 0.6),
+            TileVisual::UnclearedCombat => Color::rgb(1.0, 0.5, 0.5),
+            TileVisual::ClearedCombat => Color::rgb(0.5, 1.0, 0.5),
+            TileVisual::Subregion => Color::WHITE,
+            TileVisual::Waypoint => WAYPOINT_COLOUR,
+            TileVisual::Obstructed => Color::GRAY,
+            TileVisual::Hovered => Color::BLUE,
+            TileVisual::Path => Color::YELLOW.with_a(0.6),
+            TileVisual::Target => Color::LIME_GREEN,
+            TileVisual::Splash => Color::rgba(1.0, 0.6, 0.2, 0.8),
+            TileVisual::Danger => Color::rgba(0.9, 0.2, 0.2, 0.8),
+            TileVisual::SplashHit => Color::rgb(0.8, 0.0, 0.6),
+            TileVisual::Selection => Color::ORANGE,
+        }
+    }
+}
+
+/// How many different shades enemies can take as they lose health
+const ENEMY_HEALTH_SHADES: usize = 10;
+
+#[derive(Resource)]
+pub struct TileAssets {
+    pub hex_mesh: Handle<Mesh>,
+    /// A cliff side of height 1, scaled to the height of each raised tile
+    pub cliff_mesh: Handle<Mesh>,
+    pub cliff_material: Handle<StandardMaterial>,
+    pub enemy_mesh: Handle<Mesh>,
+    /// (visual, dimmed by the fog of war) -> material
+    tile_materials: HashMap<(TileVisual, bool), Handle<StandardMaterial>>,
+    /// Enemy materials from (nearly) dead to full health
+    enemy_materials: Vec<Handle<StandardMaterial>>,
+}
+
+impl FromWorld for TileAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let hex_mesh = meshes.add(Mesh::from(shape::RegularPolygon {
+            radius: 5.2 * SCALE,
+            sides: 6,
+        }));
+        let cliff_mesh = meshes.add(Mesh::from(shape::Cylinder {
+            radius: 5.2 * SCALE,
+            height: 1.0,
+            resolution: 6,
+            segments: 1,
+        }));
+        let enemy_mesh = meshes.add(Mesh::from(shape::Capsule {
+            radius: 1.2 * SCALE,
+            rings: 1,
+            depth: 1.0,
+            ..default()
+        }));
+
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let mut tile_materials = HashMap::new();
+        for visual in TileVisual::ALL {
+            tile_materials.insert((visual, false), materials.add(visual.colour().into()));
+            tile_materials.insert((visual, true), materials.add(fog_dim(visual.colour()).into()));
+        }
+        let enemy_materials = (1..=ENEMY_HEALTH_SHADES)
+            .map(|shade| materials.add(Color::rgba(1.0, 0.0, 0.0, shade as f32 / ENEMY_HEALTH_SHADES as f32).into()))
+            .collect();
+        let cliff_material = materials.add(CLIFF_COLOUR.into());
+
+        TileAssets {
+            hex_mesh,
+            cliff_mesh,
+            cliff_material,
+            enemy_mesh,
+            tile_materials,
+            enemy_materials,
+        }
+    }
+}
+
+impl TileAssets {
+    pub fn tile_material(&self, visual: TileVisual, dimmed: bool) -> Handle<StandardMaterial> {
+        self.tile_materials[&(visual, dimmed)].clone()
+    }
+
+    /// The material of an enemy with the given fraction of its health left
+    pub fn enemy_material(&self, health_percentage: f32) -> Handle<StandardMaterial> {
+        let shade = (health_percentage * ENEMY_HEALTH_SHADES as f32).ceil() as usize;
+        self.enemy_materials[shade.clamp(1, ENEMY_HEALTH_SHADES) - 1].clone()
+    }
+}
@@ -0,0 +1,453 @@
+use std::{collections::HashSet, fs};
+
+use bevy::window::PrimaryWindow;
+
+use crate::*;
+
+/// Plugin responsible for the world map screen, toggled with [M].
+///
+/// The map is a graph of every region the player has discovered. A region counts as discovered once it has been
+/// visited, or once a tile leading into it has been explored.
+pub struct WorldMapPlugin;
+
+impl Plugin for WorldMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            draw_world_map
+                .in_schedule(OnEnter(UIState::WorldMap))
+                .in_set(OnUpdate(GameState::InGame)),
+        )
+        .add_system(undraw_world_map.in_schedule(OnExit(UIState::WorldMap)));
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum RegionStatus {
+    /// A combat region whose enemies have all been defeated
+    Cleared,
+    /// A combat region that still has enemies in it
+    Uncleared,
+    /// A non-combat region the player has been to
+    Visited,
+    /// A non-combat region the player has seen the entrance to, but never entered
+    Seen,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegionNode {
+    pub id: String,
+    pub status: RegionStatus,
+    /// IDs of the discovered regions directly connected to this one
+    pub links: Vec<String>,
+}
+
+/// The ID of the region containing the given subregion, or `None` for a top level region
+fn parent_region_id(id: &str) -> Option<String> {
+    let split_id = id.split('.').collect::<Vec<&str>>();
+    if split_id.len() < 2 {
+        return None;
+    }
+    Some(split_id[..split_id.len() - 1].join("."))
+}
+
+/// Builds the graph of discovered regions from the saved world data, sorted by region ID
+pub fn discovered_regions(world: &HashMap<String, Region>) -> Vec<RegionNode> {
+    // (region id, subregion type of the tile it was seen through)
+    let mut discovered: Vec<(String, Option<SubregionType>)> = vec![];
+
+    for (id, region) in world {
+        if region.visited {
+            discovered.push((id.clone(), None));
+        }
+    }
+    for region in world.values().filter(|region| region.visited) {
+        for tile in region.tiles.iter().filter(|tile| tile.is_explored) {
+            if let Some(ref sub_region_data) = tile.sub_region_id {
+                match discovered.iter_mut().find(|(id, _)| *id == sub_region_data.id) {
+                    Some((_, seen_as)) => *seen_as = Some(sub_region_data.subregion_type.clone()),
+                    None => discovered.push((sub_region_data.id.clone(), Some(sub_region_data.subregion_type.clone()))),
+                }
+            }
+        }
+    }
+
+    let is_discovered = |id: &String| discovered.iter().any(|(discovered_id, _)| discovered_id == id);
+
+    let mut nodes: Vec<RegionNode> = discovered
+        .iter()
+        .map(|(id, seen_as)| {
+            let region = world.get(id);
+
+            let status = match region {
+                Some(region) if region.cleared => RegionStatus::Cleared,
+                Some(region) if region.enemies.is_some() => RegionStatus::Uncleared,
+                _ if *seen_as == Some(SubregionType::UnclearedCombat) => RegionStatus::Uncleared,
+                _ if *seen_as == Some(SubregionType::ClearedCombat) => RegionStatus::Cleared,
+                Some(region) if region.visited => RegionStatus::Visited,
+                _ => RegionStatus::Seen,
+            };
+
+            // Regions are linked to their parent by ID, and to any subregion reachable through one of their tiles
+            let mut links: Vec<String> = world
+                .keys()
+                .filter(|other| {
+                    parent_region_id(other).as_ref() == Some(id) || parent_region_id(id).as_ref() == Some(*other)
+                })
+                .cloned()
+                .collect();
+            if let Some(region) = region {
+                for tile in &region.tiles {
+                    if let Some(ref sub_region_data) = tile.sub_region_id {
+                        if !links.contains(&sub_region_data.id) {
+                            links.push(sub_region_data.id.clone());
+                        }
+                    }
+                }
+            }
+            links.retain(|link| is_discovered(link));
+            links.sort();
+
+            RegionNode {
+                id: id.clone(),
+                status,
+                links,
+            }
+        })
+        .collect();
+
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    nodes
+}
+
+/// Where each node sits on the map, as fractions of the map's width and height. Each level of subregion gets its own
+/// row below the region containing it, and the nodes in a row are spread evenly across it
+pub fn node_positions(nodes: &[RegionNode]) -> HashMap<String, Vec2> {
+    let depth = |node: &RegionNode| node.id.split('.').count();
+    let max_depth = nodes.iter().map(depth).max().unwrap_or(1);
+
+    let mut positions = HashMap::new();
+    for row_depth in 1..=max_depth {
+        let row: Vec<&RegionNode> = nodes.iter().filter(|node| depth(node) == row_depth).collect();
+        for (i, node) in row.iter().enumerate() {
+            positions.insert(
+                node.id.clone(),
+                Vec2::new(
+                    (i as f32 + 1.0) / (row.len() as f32 + 1.0),
+                    (row_depth as f32 - 0.5) / max_depth as f32,
+                ),
+            );
+        }
+    }
+    positions
+}
+
+/// Thickness of the lines drawn between linked regions, in pixels
+const LINK_THICKNESS: f32 = 3.0;
+
+#[derive(Component)]
+struct WorldMapUIRoot;
+
+fn draw_world_map(
+    mut commands: Commands,
+    map_context: Res<MapContext>,
+    player: Query<&Player>,
+    opt_combat_manager: Option<Res<CombatManager>>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    asset_server: Res<AssetServer>,
+    mut unsaved_exploration: ResMut<UnsavedExploration>,
+) {
+    // Tiles explored in the last few seconds may not have been saved yet
+    unsaved_exploration.flush();
+
+    let contents = fs::read_to_string("world.json").expect("Something went wrong reading the file");
+    let world: HashMap<String, Region> = serde_json::from_str(&contents).unwrap();
+    let nodes = discovered_regions(&world);
+
+    let mut map_width = primary_window.single().resolution.width() / 2.0;
+    let mut map_height = map_width / (1920.0 / 1080.0);
+
+    if map_height > primary_window.single().resolution.height() {
+        map_height = primary_window.single().resolution.height() / 2.0;
+        map_width = map_height * (1920.0 / 1080.0);
+    }
+
+    let graph_size = Vec2::new(map_width, map_height / 2.0);
+    let positions: HashMap<String, Vec2> = node_positions(&nodes)
+        .into_iter()
+        .map(|(id, position)| (id, position * graph_size))
+        .collect();
+
+    let max_depth = nodes.iter().map(|node| node.id.split('.').count()).max().unwrap_or(1);
+    let widest_row = (1..=max_depth)
+        .map(|depth| nodes.iter().filter(|node| node.id.split('.').count() == depth).count())
+        .max()
+        .unwrap_or(1);
+    let node_size = Vec2::new(
+        (graph_size.x / (widest_row as f32 + 1.0)).min(map_width / 6.0),
+        (graph_size.y / max_depth as f32 * 0.6).min(map_height / 6.0),
+    );
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.9).into(),
+            ..default()
+        })
+        .insert(Name::new("WorldMapUIRoot"))
+        .insert(WorldMapUIRoot)
+        .with_children(|commands| {
+            commands
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(map_width), Val::Px(map_height)),
+                        flex_direction: FlexDirection::Column,
+                        justify_content: JustifyContent::SpaceEvenly,
+                        align_items: AlignItems::Center,
+                        align_self: AlignSelf::Center,
+                        margin: UiRect::left(Val::Px(
+                            // Offset required for the centre of the map width to align with centre of screen
+                            (primary_window.single().resolution.width() - map_width) / 2.0,
+                        )),
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.13, 0.14, 0.26).into(),
+                    ..default()
+                })
+                .insert(Name::new("World map layout"))
+                .with_children(|commands| {
+                    commands.spawn(TextBundle::from_section(
+                        "World Map",
+                        TextStyle {
+                            font: asset_server.load("font.otf"),
+                            font_size: map_width / 30.0,
+                            color: Color::WHITE,
+                        },
+                    ));
+
+                    commands
+                        .spawn(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(graph_size.x), Val::Px(graph_size.y)),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .insert(Name::new("Region graph"))
+                        .with_children(|commands| {
+                            // A link can be listed by either of its ends, or both
+                            let mut links: HashSet<(&String, &String)> = HashSet::new();
+                            for node in &nodes {
+                                for link in &node.links {
+                                    links.insert((link.min(&node.id), link.max(&node.id)));
+                                }
+                            }
+                            // Links are spawned first so the nodes are drawn on top of them
+                            for (from, to) in links {
+                                spawn_region_link(commands, positions[from], positions[to]);
+                            }
+                            for node in &nodes {
+                                spawn_region_node(
+                                    commands,
+                                    &asset_server,
+                                    node,
+                                    node.id == map_context.id,
+                                    positions[&node.id],
+                                    node_size,
+                                    map_width,
+                                );
+                            }
+                        });
+
+                    // Fast travel destinations
+                    commands
+                        .spawn(NodeBundle {
+                            style: Style {
+                                size: Size::width(Val::Percent(100.0)),
+                                flex_direction: FlexDirection::Row,
+                                flex_wrap: FlexWrap::Wrap,
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .insert(Name::new("Waypoints"))
+                        .with_children(|commands| {
+                            let waypoints = player.get_single().map(|p| p.waypoints.clone()).unwrap_or_default();
+
+                            let heading = if opt_combat_manager.is_some() {
+                                "Fast travel is unavailable during combat"
+                            } else if waypoints.is_empty() {
+                                "No waypoints discovered"
+                            } else {
+                                "Fast travel:"
+                            };
+                            commands.spawn(TextBundle::from_section(
+                                heading,
+                                TextStyle {
+                                    font: asset_server.load("font.otf"),
+                                    font_size: map_width / 45.0,
+                                    color: Color::WHITE,
+                                },
+                            ));
+
+                            if opt_combat_manager.is_some() {
+                                return;
+                            }
+
+                            for waypoint in waypoints {
+                                commands
+                                    .spawn(ButtonBundle {
+                                        style: Style {
+                                            padding: UiRect::all(Val::Px(map_width / 100.0)),
+                                            margin: UiRect::all(Val::Px(map_width / 100.0)),
+                                            ..default()
+                                        },
+                                        background_color: Color::rgb(0.22, 0.25, 0.48).into(),
+                                        ..default()
+                                    })
+                                    .with_children(|commands| {
+                                        commands.spawn(TextBundle::from_section(
+                                            format!(
+                                                "Region {} ({}, {})",
+                                                waypoint.world, waypoint.coord.q, waypoint.coord.r
+                                            ),
+                                            TextStyle {
+                                                font: asset_server.load("font.otf"),
+                                                font_size: map_width / 50.0,
+                                                color: Color::WHITE,
+                                            },
+                                        ));
+                                    })
+                                    .insert(FastTravelButton(waypoint));
+                            }
+                        });
+
+                    commands.spawn(TextBundle::from_section(
+                        "Press [M] or [ESC] to close",
+                        TextStyle {
+                            font: asset_server.load("font.otf"),
+                            font_size: map_width / 45.0,
+                            color: Color::rgba(1.0, 1.0, 1.0, 0.6),
+                        },
+                    ));
+                });
+        });
+}
+
+fn spawn_region_node(
+    commands: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    node: &RegionNode,
+    is_current: bool,
+    centre: Vec2,
+    size: Vec2,
+    base_width: f32,
+) {
+    let colour = match node.status {
+        RegionStatus::Cleared => Color::rgb(0.2, 0.6, 0.3),
+        RegionStatus::Uncleared => Color::rgb(0.7, 0.2, 0.2),
+        RegionStatus::Visited => Color::rgb(0.22, 0.25, 0.48),
+        RegionStatus::Seen => Color::rgb(0.3, 0.3, 0.3),
+    };
+
+    let mut label = format!("Region {}\n{:?}", node.id, node.status);
+    if is_current {
+        label.push_str("\n(You are here)");
+    }
+
+    // The outer node acts as a border, which is highlighted for the region the player is currently in
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(centre.x - size.x / 2.0),
+                    top: Val::Px(centre.y - size.y / 2.0),
+                    ..default()
+                },
+                size: Size::new(Val::Px(size.x), Val::Px(size.y)),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            background_color: if is_current { Color::YELLOW } else { colour }.into(),
+            ..default()
+        })
+        .insert(Name::new(format!("Region {} node", node.id)))
+        .with_children(|commands| {
+            commands
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                        padding: UiRect::all(Val::Px(base_width / 80.0)),
+                        ..default()
+                    },
+                    background_color: colour.into(),
+                    ..default()
+                })
+                .with_children(|commands| {
+                    commands.spawn(TextBundle::from_section(
+                        label,
+                        TextStyle {
+                            font: asset_server.load("font.otf"),
+                            font_size: base_width / 60.0,
+                            color: Color::WHITE,
+                        },
+                    ));
+                });
+        });
+}
+
+/// Draws a line between the centres of two region nodes
+fn spawn_region_link(commands: &mut ChildBuilder, from: Vec2, to: Vec2) {
+    let delta = to - from;
+    let centre = (from + to) / 2.0;
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(centre.x - delta.length() / 2.0),
+                top: Val::Px(centre.y - LINK_THICKNESS / 2.0),
+                ..default()
+            },
+            size: Size::new(Val::Px(delta.length()), Val::Px(LINK_THICKNESS)),
+            ..default()
+        },
+        background_color: Color::rgba(1.0, 1.0, 1.0, 0.6).into(),
+        // The UI layout only sets the translation, so the rotation is kept. UI space has y pointing down
+        transform: Transform::from_rotation(Quat::from_rotation_z(delta.y.atan2(delta.x))),
+        ..default()
+    });
+}
+
+fn undraw_world_map(mut commands: Commands, ui_root: Query<Entity, With<WorldMapUIRoot>>) {
+    commands.entity(ui_root.single()).despawn_recursive();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str) -> RegionNode {
+        RegionNode {
+            id: id.to_string(),
+            status: RegionStatus::Visited,
+            links: vec![],
+        }
+    }
+
+    #[test]
+    fn subregions_sit_a_row_below_their_region() {
+        let positions = node_positions(&[node("0"), node("0.0"), node("0.1"), node("1")]);
+
+        assert_eq!(positions["0"], Vec2::new(1.0 / 3.0, 0.25));
+        assert_eq!(positions["1"], Vec2::new(2.0 / 3.0, 0.25));
+        assert_eq!(positions["0.0"], Vec2::new(1.0 / 3.0, 0.75));
+        assert_eq!(positions["0.1"], Vec2::new(2.0 / 3.0, 0.75));
+    }
+}
@@ -8,6 +8,14 @@ pub struct Tile {
     pub is_obstructed: bool,
     pub can_be_clicked: bool,
     pub sub_region_id: Option<SubregionData>,
+    #[serde(default)]
+    pub elevation: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prop: Option<TileProp>,
+    #[serde(default)]
+    pub is_waypoint: bool,
+    #[serde(default)]
+    pub is_explored: bool,
     #[serde(default, skip_serializing)]
     pub is_hovered: bool,
     #[serde(default, skip_serializing)]
@@ -20,11 +28,38 @@ impl Tile {
             is_obstructed,
             can_be_clicked: false,
             sub_region_id,
+            elevation: 0,
+            prop: None,
+            is_waypoint: false,
+            is_explored: false,
             is_hovered: false,
             is_clicked: false,
         }
     }
 }
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TileProp {
+    pub kind: String,
+    pub rotation: f32,
+    pub scale: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inscription: Option<String>,
+}
+impl TileProp {
+    pub fn new(kind: &str, rotation: f32, scale: f32) -> TileProp {
+        TileProp {
+            kind: kind.to_string(),
+            rotation,
+            scale,
+            inscription: None,
+        }
+    }
+
+    pub fn with_inscription(mut self, inscription: &str) -> TileProp {
+        self.inscription = Some(inscription.to_string());
+        self
+    }
+}
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 pub struct HexCoord {
     pub q: i32,
@@ -78,6 +113,58 @@ impl Health {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RoamBehaviour {
+    Patrol { route: Vec<HexCoord> },
+    Wander { radius: i32 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoamingGroup {
+    pub id: String,
+    pub hex_coord: HexCoord,
+    pub behaviour: RoamBehaviour,
+    pub aggro_radius: i32,
+    pub enemies: Vec<Enemy>,
+    #[serde(default)]
+    pub combat_region: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EncounterTable {
+    pub chance: f32,
+    pub groups: Vec<EncounterGroup>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EncounterGroup {
+    pub weight: u32,
+    pub enemies: Vec<EnemyTemplate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnemyTemplate {
+    pub attack_range: i32,
+    pub movement_range: i32,
+    pub damage: f32,
+    pub hp: f32,
+    pub min_count: u32,
+    pub max_count: u32,
+}
+
+impl EnemyTemplate {
+    pub fn new(attack_range: i32, movement_range: i32, damage: f32, hp: f32, min_count: u32, max_count: u32) -> Self {
+        EnemyTemplate {
+            attack_range,
+            movement_range,
+            damage,
+            hp,
+            min_count,
+            max_count,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Chest {
     pub hex_coord: HexCoord,
@@ -91,6 +178,41 @@ pub struct Region {
     pub enemies: Option<Vec<Enemy>>,
     pub player_spawn_spot: HexCoord,
     pub chests: Option<Vec<Chest>>,
+    #[serde(default)]
+    pub environment: Option<EnvironmentSettings>,
+    #[serde(default)]
+    pub visited: bool,
+    #[serde(default)]
+    pub cleared: bool,
+    #[serde(default)]
+    pub roaming_groups: Vec<RoamingGroup>,
+    #[serde(default)]
+    pub encounters: Option<EncounterTable>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EnvironmentSettings {
+    pub clear_colour: [f32; 3],
+    pub ambient_colour: [f32; 3],
+    pub ambient_brightness: f32,
+    pub directional_light: Option<DirectionalLightSettings>,
+    pub fog: Option<FogSettingsData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectionalLightSettings {
+    pub colour: [f32; 3],
+    pub illuminance: f32,
+    pub direction: [f32; 3],
+    #[serde(default)]
+    pub shadows_enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FogSettingsData {
+    pub colour: [f32; 4],
+    pub start: f32,
+    pub end: f32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -106,6 +228,9 @@ pub enum SubregionType {
     Other,
 }
 
+/// Width and height of a region's walkable area, in tiles. Every region is surrounded by a ring of obstructed tiles
+const DEFAULT_REGION_SIZE: i32 = 6;
+
 fn main() {
     // Overarching Hashmap that is going to be written to the .json file at the end
     let mut map: HashMap<String, Region> = HashMap::new();
@@ -123,6 +248,29 @@ fn main() {
     // Chest data
     let mut chest_locations: HashMap<String, Option<Vec<Chest>>> = HashMap::new();
 
+    // How each region looks. Regions without an entry use the game's default environment
+    let mut region_environments: HashMap<String, EnvironmentSettings> = HashMap::new();
+
+    // Waypoint tiles that the player can fast travel between, in (region id - tile coords) pairs
+    let mut waypoint_locations: HashMap<String, Vec<(i32, i32)>> = HashMap::new();
+
+    // Heights of raised tiles, in (region id - (tile coords - elevation)) pairs. Unlisted tiles are at elevation 0
+    let mut elevation_locations: HashMap<String, HashMap<(i32, i32), i32>> = HashMap::new();
+
+    // Regions that aren't DEFAULT_REGION_SIZE tiles across, in (region id - size) pairs. The game streams large
+    // regions in chunks, so sizes of up to 200 are fine, e.g. `region_sizes.insert("1".to_string(), 200);`
+    let region_sizes: HashMap<String, i32> = HashMap::new();
+
+    // Decorations placed on tiles, in (region id - (tile coords - prop)) pairs. The kinds are listed in assets/props.json
+    let mut prop_locations: HashMap<String, HashMap<(i32, i32), TileProp>> = HashMap::new();
+
+    // Enemy groups walking around non-combat regions, in (region id - groups) pairs. Groups without a combat region
+    // are fought in an arena built on the spot, and their enemies are placed there
+    let mut roaming_group_locations: HashMap<String, Vec<RoamingGroup>> = HashMap::new();
+
+    // Random encounters rolled for every step the player takes in a region, in (region id - table) pairs
+    let mut encounter_tables: HashMap<String, EncounterTable> = HashMap::new();
+
     // Example: Lets create the overworld ("1") with 2 subregions ("1.1", "1.2")
     // To do this, we need to first need to define what tiles will house these subregions
     tile_subregion_ids.insert(
@@ -170,19 +318,124 @@ fn main() {
     chest_locations.insert("1.1".to_string(), None);
     chest_locations.insert("1.2".to_string(), None);
 
+    // Make the combat subregion "1.1" a gloomy, foggy place lit by a low red sun
+    region_environments.insert(
+        "1.1".to_string(),
+        EnvironmentSettings {
+            clear_colour: [0.25, 0.2, 0.22],
+            ambient_colour: [1.0, 0.85, 0.85],
+            ambient_brightness: 0.3,
+            directional_light: Some(DirectionalLightSettings {
+                colour: [1.0, 0.6, 0.5],
+                illuminance: 8000.0,
+                direction: [1.0, -0.5, 0.3],
+                shadows_enabled: true,
+            }),
+            fog: Some(FogSettingsData {
+                colour: [0.25, 0.2, 0.22, 1.0],
+                start: 20.0,
+                end: 60.0,
+            }),
+        },
+    );
+
+    // Waypoints at the spawn spots of the overworld and the non-combat subregion. Combat regions shouldn't have any
+    waypoint_locations.insert("1".to_string(), vec![(0, 0)]);
+    waypoint_locations.insert("1.2".to_string(), vec![(2, 3)]);
+
+    // Give the combat subregion "1.1" a hill. Its peak can only be climbed from the slope at (2, 3)
+    elevation_locations.insert(
+        "1.1".to_string(),
+        HashMap::from([((2, 2), 1), ((3, 2), 1), ((2, 3), 1), ((3, 3), 2)]),
+    );
+
+    // Line the overworld's border with trees and rocks, and leave some crates next to the chest
+    prop_locations.insert(
+        "1".to_string(),
+        HashMap::from([
+            ((-1, -1), TileProp::new("tree", 0.0, 1.0)),
+            ((2, -1), TileProp::new("rock", 1.2, 0.8)),
+            ((6, 0), TileProp::new("tree", 2.1, 1.2)),
+            ((-1, 3), TileProp::new("rock", 0.4, 1.0)),
+            ((3, 6), TileProp::new("tree", 4.0, 0.9)),
+            (
+                (6, 6),
+                TileProp::new("pillar", 0.0, 1.0).with_inscription("The old road ends here."),
+            ),
+            ((5, 2), TileProp::new("crate", 0.5, 1.0)),
+        ]),
+    );
+
+    // A lone enemy patrols the bottom of the overworld, and a pair of archers wander around "1.2"
+    roaming_group_locations.insert(
+        "1".to_string(),
+        vec![RoamingGroup {
+            id: "patrol".to_string(),
+            hex_coord: HexCoord::new(4, 4),
+            behaviour: RoamBehaviour::Patrol {
+                route: vec![HexCoord::new(4, 4), HexCoord::new(1, 4)],
+            },
+            aggro_radius: 2,
+            enemies: vec![Enemy::new(0, 0, 1, 2, 5.0, 10.0)],
+            combat_region: None,
+        }],
+    );
+    roaming_group_locations.insert(
+        "1.2".to_string(),
+        vec![RoamingGroup {
+            id: "wanderers".to_string(),
+            hex_coord: HexCoord::new(4, 1),
+            behaviour: RoamBehaviour::Wander { radius: 1 },
+            aggro_radius: 1,
+            enemies: vec![Enemy::new(0, 0, 2, 1, 5.0, 8.0), Enemy::new(0, 0, 2, 1, 5.0, 8.0)],
+            combat_region: None,
+        }],
+    );
+
+    // Walking around the overworld now and then runs into a pair of brawlers, or a brawler with an archer
+    encounter_tables.insert(
+        "1".to_string(),
+        EncounterTable {
+            chance: 0.05,
+            groups: vec![
+                EncounterGroup {
+                    weight: 3,
+                    enemies: vec![EnemyTemplate::new(1, 2, 4.0, 8.0, 1, 2)],
+                },
+                EncounterGroup {
+                    weight: 1,
+                    enemies: vec![
+                        EnemyTemplate::new(1, 2, 4.0, 8.0, 1, 1),
+                        EnemyTemplate::new(2, 1, 5.0, 6.0, 1, 1),
+                    ],
+                },
+            ],
+        },
+    );
+
     // Generates a world based on data provided in hashmaps
     for (key, value) in region_subregion_ids.iter() {
         let mut tile_vec: Vec<Tile> = Vec::new();
         let mut enemy_vec: Vec<Enemy> = Vec::new();
         let mut chest_vec: Vec<Chest> = Vec::new();
 
-        for q in -1..=6 {
-            for r in -1..=6 {
+        let size = *region_sizes.get(key).unwrap_or(&DEFAULT_REGION_SIZE);
+        for q in -1..=size {
+            for r in -1..=size {
                 let mut obstructed = false;
-                if q == -1 || q == 6 || r == -1 || r == 6 {
+                if q == -1 || q == size || r == -1 || r == size {
                     obstructed = true
                 }
                 let mut current_tile = Tile::new(q, r, obstructed, None);
+                if let Some(waypoints) = waypoint_locations.get(key) {
+                    current_tile.is_waypoint = waypoints.contains(&(q, r));
+                }
+                if let Some(elevations) = elevation_locations.get(key) {
+                    current_tile.elevation = *elevations.get(&(q, r)).unwrap_or(&0);
+                }
+                if let Some(props) = prop_locations.get(key) {
+                    current_tile.prop = props.get(&(q, r)).cloned();
+                }
                 if let Some(subregions) = value {
                     for (coord, id) in subregions {
                         if (q, r) == *coord {
@@ -220,6 +473,11 @@ fn main() {
             enemies,
             player_spawn_spot: HexCoord::new_from_tupple(*region_spawn_position.get(key).clone().unwrap()),
             chests,
+            environment: region_environments.get(key).cloned(),
+            visited: false,
+            cleared: false,
+            roaming_groups: roaming_group_locations.get(key).cloned().unwrap_or_default(),
+            encounters: encounter_tables.get(key).cloned(),
         };
 
         map.insert(key.clone(), current_region);